@@ -0,0 +1,168 @@
+use super::{
+    format::{convert_input, convert_output, with_sample_type},
+    AudioStream, Backend, ErrorCallback, InputCallback, OutputCallback,
+};
+use anyhow::Context;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+/// Backend for real audio devices of a cpal host.
+pub struct CpalBackend {
+    host: cpal::Host,
+}
+
+impl Default for CpalBackend {
+    fn default() -> Self {
+        Self::new(cpal::default_host())
+    }
+}
+
+impl CpalBackend {
+    pub fn new(host: cpal::Host) -> Self {
+        Self { host }
+    }
+
+    /// Opens the host with the given name, case-insensitively, or the platform default for
+    /// `"default"`.
+    pub fn from_host_name(name: &str) -> anyhow::Result<Self> {
+        if name == "default" {
+            return Ok(Self::default());
+        }
+        let id = cpal::ALL_HOSTS
+            .iter()
+            .copied()
+            .find(|x| x.name().eq_ignore_ascii_case(name))
+            .with_context(|| {
+                let mut message = format!(
+                    "audio host '{}' is not compiled into this build; compiled hosts: {}",
+                    name,
+                    host_names(cpal::ALL_HOSTS)
+                );
+                if name.eq_ignore_ascii_case("jack") {
+                    message.push_str(" (rebuild with `--features jack` for JACK support)");
+                }
+                message
+            })?;
+        if !cpal::available_hosts().contains(&id) {
+            anyhow::bail!(
+                "audio host '{}' is compiled in but not available on this system; available hosts: {}",
+                id.name(),
+                host_names(&cpal::available_hosts())
+            );
+        }
+        let host = cpal::host_from_id(id)
+            .with_context(|| format!("could not open audio host '{}'", id.name()))?;
+        Ok(Self::new(host))
+    }
+
+    pub fn host(&self) -> &cpal::Host {
+        &self.host
+    }
+}
+
+impl Backend for CpalBackend {
+    type Device = cpal::Device;
+
+    fn input_devices(&self) -> anyhow::Result<Vec<Self::Device>> {
+        Ok(self.host.input_devices()?.collect())
+    }
+
+    fn output_devices(&self) -> anyhow::Result<Vec<Self::Device>> {
+        Ok(self.host.output_devices()?.collect())
+    }
+
+    fn default_input_device(&self) -> Option<Self::Device> {
+        self.host.default_input_device()
+    }
+
+    fn default_output_device(&self) -> Option<Self::Device> {
+        self.host.default_output_device()
+    }
+
+    fn device_name(&self, device: &Self::Device) -> anyhow::Result<String> {
+        Ok(device.name()?)
+    }
+
+    fn supported_input_configs(
+        &self,
+        device: &Self::Device,
+    ) -> anyhow::Result<Vec<cpal::SupportedStreamConfigRange>> {
+        Ok(device.supported_input_configs()?.collect())
+    }
+
+    fn supported_output_configs(
+        &self,
+        device: &Self::Device,
+    ) -> anyhow::Result<Vec<cpal::SupportedStreamConfigRange>> {
+        Ok(device.supported_output_configs()?.collect())
+    }
+
+    fn default_input_config(
+        &self,
+        device: &Self::Device,
+    ) -> anyhow::Result<cpal::SupportedStreamConfig> {
+        Ok(device.default_input_config()?)
+    }
+
+    fn default_output_config(
+        &self,
+        device: &Self::Device,
+    ) -> anyhow::Result<cpal::SupportedStreamConfig> {
+        Ok(device.default_output_config()?)
+    }
+
+    fn build_input_stream(
+        &self,
+        device: &Self::Device,
+        config: &cpal::StreamConfig,
+        format: cpal::SampleFormat,
+        data: InputCallback,
+        error: ErrorCallback,
+    ) -> anyhow::Result<Box<dyn AudioStream>> {
+        let stream = with_sample_type!(format, T => {
+            let mut data = convert_input::<T>(data, config);
+            device.build_input_stream(
+                config,
+                move |samples: &[T], _: &cpal::InputCallbackInfo| data(samples),
+                error,
+                None,
+            )?
+        });
+        Ok(Box::new(CpalStream(stream)))
+    }
+
+    fn build_output_stream(
+        &self,
+        device: &Self::Device,
+        config: &cpal::StreamConfig,
+        format: cpal::SampleFormat,
+        data: OutputCallback,
+        error: ErrorCallback,
+    ) -> anyhow::Result<Box<dyn AudioStream>> {
+        let stream = with_sample_type!(format, T => {
+            let mut data = convert_output::<T>(data, config, format);
+            device.build_output_stream(
+                config,
+                move |samples: &mut [T], _: &cpal::OutputCallbackInfo| data(samples),
+                error,
+                None,
+            )?
+        });
+        Ok(Box::new(CpalStream(stream)))
+    }
+}
+
+fn host_names(ids: &[cpal::HostId]) -> String {
+    ids.iter().map(|x| x.name()).collect::<Vec<_>>().join(", ")
+}
+
+struct CpalStream(cpal::Stream);
+
+impl AudioStream for CpalStream {
+    fn play(&self) -> anyhow::Result<()> {
+        Ok(self.0.play()?)
+    }
+
+    fn pause(&self) -> anyhow::Result<()> {
+        Ok(self.0.pause()?)
+    }
+}
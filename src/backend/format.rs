@@ -0,0 +1,161 @@
+//! Conversion between the `f32` samples of the route and the sample format of a device.
+
+use super::{InputCallback, OutputCallback};
+use cpal::{BufferSize, FromSample, Sample, SampleFormat, SizedSample, StreamConfig};
+
+/// Integer formats up to this many bits are dithered. Wider ones already hold more precision
+/// than an `f32` carries.
+const MAX_DITHERED_BITS: usize = 24;
+/// Smallest callback size the conversion buffers are sized for, also with a fixed buffer size
+/// since drivers do not always keep to it. It covers the largest periods common drivers use;
+/// larger callbacks are converted in chunks.
+const MIN_BLOCK_FRAMES: usize = 16384;
+
+/// Expands `$body` with the type `$T` bound to the sample type of `$format`, and fails for
+/// formats without a sample type.
+macro_rules! with_sample_type {
+    ($format:expr, $T:ident => $body:expr) => {
+        match $format {
+            cpal::SampleFormat::I8 => {
+                type $T = i8;
+                $body
+            }
+            cpal::SampleFormat::I16 => {
+                type $T = i16;
+                $body
+            }
+            cpal::SampleFormat::I32 => {
+                type $T = i32;
+                $body
+            }
+            cpal::SampleFormat::I64 => {
+                type $T = i64;
+                $body
+            }
+            cpal::SampleFormat::U8 => {
+                type $T = u8;
+                $body
+            }
+            cpal::SampleFormat::U16 => {
+                type $T = u16;
+                $body
+            }
+            cpal::SampleFormat::U32 => {
+                type $T = u32;
+                $body
+            }
+            cpal::SampleFormat::U64 => {
+                type $T = u64;
+                $body
+            }
+            cpal::SampleFormat::F32 => {
+                type $T = f32;
+                $body
+            }
+            cpal::SampleFormat::F64 => {
+                type $T = f64;
+                $body
+            }
+            format => anyhow::bail!("unsupported sample format {}", format),
+        }
+    };
+}
+pub(crate) use with_sample_type;
+
+/// Samples in the conversion buffer of a stream opened with `config`.
+fn block_samples(config: &StreamConfig) -> usize {
+    let frames = match config.buffer_size {
+        BufferSize::Fixed(frames) => (frames as usize).max(MIN_BLOCK_FRAMES),
+        BufferSize::Default => MIN_BLOCK_FRAMES,
+    };
+    frames * config.channels as usize
+}
+
+/// Adapts an `f32` input callback to a device that captures `T` samples with `config`.
+pub(crate) fn convert_input<T>(
+    mut data: InputCallback,
+    config: &StreamConfig,
+) -> impl FnMut(&[T]) + Send + 'static
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let mut buffer = vec![0.0; block_samples(config)];
+    move |samples: &[T]| {
+        for chunk in samples.chunks(buffer.len()) {
+            let buffer = &mut buffer[..chunk.len()];
+            for (out, &x) in buffer.iter_mut().zip(chunk) {
+                *out = f32::from_sample(x);
+            }
+            data(buffer);
+        }
+    }
+}
+
+/// Adapts an `f32` output callback to a device that plays `T` samples with `config`, with dither
+/// when `format` is a narrow integer format.
+pub(crate) fn convert_output<T>(
+    mut data: OutputCallback,
+    config: &StreamConfig,
+    format: SampleFormat,
+) -> impl FnMut(&mut [T]) + Send + 'static
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let mut buffer = vec![0.0; block_samples(config)];
+    let mut dither = Dither::new(format);
+    move |samples: &mut [T]| {
+        for chunk in samples.chunks_mut(buffer.len()) {
+            let buffer = &mut buffer[..chunk.len()];
+            data(buffer);
+            match &mut dither {
+                Some(dither) => {
+                    for (out, &x) in chunk.iter_mut().zip(buffer.iter()) {
+                        *out = T::from_sample(dither.quantize(x));
+                    }
+                }
+                None => {
+                    for (out, &x) in chunk.iter_mut().zip(buffer.iter()) {
+                        *out = T::from_sample(x);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Triangular dither of one least significant bit, added before rounding to an integer format.
+/// It turns the rounding error into a constant noise floor instead of distortion that follows
+/// the signal, which is audible on quiet sidetone through 16-bit headsets.
+pub(crate) struct Dither {
+    /// Integer steps per unit of `f32` amplitude.
+    scale: f32,
+    /// Xorshift state, never zero.
+    state: u32,
+}
+
+impl Dither {
+    /// A dither for `format`, or `None` for float and wide integer formats.
+    pub(crate) fn new(format: SampleFormat) -> Option<Self> {
+        let bits = format.sample_size() * 8;
+        ((format.is_int() || format.is_uint()) && bits <= MAX_DITHERED_BITS).then(|| Self {
+            scale: (1_u32 << (bits - 1)) as f32,
+            state: 0x9e37_79b9,
+        })
+    }
+
+    /// Dithers `x` and rounds it to the nearest step of the integer format, within its range.
+    pub(crate) fn quantize(&mut self, x: f32) -> f32 {
+        let noise = self.uniform() - self.uniform();
+        let step = (x * self.scale + noise).round();
+        step.clamp(-self.scale, self.scale - 1.0) / self.scale
+    }
+
+    /// A uniformly distributed value in `0.0..1.0`.
+    fn uniform(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        (self.state >> 8) as f32 / (1 << 24) as f32
+    }
+}
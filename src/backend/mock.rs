@@ -0,0 +1,529 @@
+//! Deterministic in-memory backend for driving a route without a sound card.
+//!
+//! Nothing runs on its own: every call to [`MockBackend::run_cycle`] feeds the next scripted
+//! input buffer to the input stream and then asks the output stream for one block, which is
+//! appended to the captured output. In loopback mode the output is also fed back to the input
+//! stream after a fixed delay, like a cable from the headphone jack to the microphone input.
+
+use super::{
+    format::{convert_input, convert_output, with_sample_type},
+    AudioStream, Backend, ErrorCallback, InputCallback, OutputCallback,
+};
+use cpal::{FromSample, Sample, SizedSample};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+};
+
+const DEFAULT_PERIOD: usize = 64;
+const BUFFER_SIZE_RANGE: cpal::SupportedBufferSize =
+    cpal::SupportedBufferSize::Range { min: 16, max: 8192 };
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockDevice {
+    pub name: String,
+    /// The default config.
+    pub config: cpal::StreamConfig,
+    /// The sample format of the default config.
+    pub sample_format: cpal::SampleFormat,
+    pub supported: Vec<cpal::SupportedStreamConfigRange>,
+}
+
+impl MockDevice {
+    /// A device that supports exactly its default config, in `f32`, with 16 to 8192 frame
+    /// buffers.
+    pub fn new(name: impl Into<String>, channels: u16, sample_rate: u32) -> Self {
+        Self {
+            name: name.into(),
+            config: cpal::StreamConfig {
+                channels,
+                sample_rate: cpal::SampleRate(sample_rate),
+                buffer_size: cpal::BufferSize::Default,
+            },
+            sample_format: cpal::SampleFormat::F32,
+            supported: vec![cpal::SupportedStreamConfigRange::new(
+                channels,
+                cpal::SampleRate(sample_rate),
+                cpal::SampleRate(sample_rate),
+                BUFFER_SIZE_RANGE,
+                cpal::SampleFormat::F32,
+            )],
+        }
+    }
+
+    /// Makes `format` the only sample format of the device. Streams in another format fail to
+    /// build, and streams in an integer format quantise what passes through them.
+    pub fn with_sample_format(mut self, format: cpal::SampleFormat) -> Self {
+        self.sample_format = format;
+        for range in &mut self.supported {
+            *range = cpal::SupportedStreamConfigRange::new(
+                range.channels(),
+                range.min_sample_rate(),
+                range.max_sample_rate(),
+                *range.buffer_size(),
+                format,
+            );
+        }
+        self
+    }
+
+    /// The default config with its sample format and buffer size range.
+    fn default_config(&self) -> cpal::SupportedStreamConfig {
+        let buffer_size = self
+            .supported
+            .first()
+            .map(|x| *x.buffer_size())
+            .unwrap_or(cpal::SupportedBufferSize::Unknown);
+        cpal::SupportedStreamConfig::new(
+            self.config.channels,
+            self.config.sample_rate,
+            buffer_size,
+            self.sample_format,
+        )
+    }
+
+    fn check_format(&self, format: cpal::SampleFormat) -> anyhow::Result<()> {
+        if !self.supported.iter().any(|x| x.sample_format() == format) {
+            anyhow::bail!(
+                "device '{}' does not support sample format {}",
+                self.name,
+                format
+            );
+        }
+        Ok(())
+    }
+
+    /// Adds a supported config range, in the sample format and buffer size range of the
+    /// default one.
+    pub fn with_supported_config(mut self, channels: u16, min_rate: u32, max_rate: u32) -> Self {
+        let buffer_size = self
+            .supported
+            .first()
+            .map(|x| *x.buffer_size())
+            .unwrap_or(BUFFER_SIZE_RANGE);
+        self.supported.push(cpal::SupportedStreamConfigRange::new(
+            channels,
+            cpal::SampleRate(min_rate),
+            cpal::SampleRate(max_rate),
+            buffer_size,
+            self.sample_format,
+        ));
+        self
+    }
+
+    /// Replaces the buffer size range of every supported config.
+    pub fn with_buffer_size_range(mut self, buffer_size: cpal::SupportedBufferSize) -> Self {
+        for range in &mut self.supported {
+            *range = cpal::SupportedStreamConfigRange::new(
+                range.channels(),
+                range.min_sample_rate(),
+                range.max_sample_rate(),
+                buffer_size,
+                range.sample_format(),
+            );
+        }
+        self
+    }
+}
+
+/// A cheaply cloneable handle; clones share the same devices, script and streams.
+#[derive(Clone, Default)]
+pub struct MockBackend {
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    input_devices: Vec<MockDevice>,
+    output_devices: Vec<MockDevice>,
+    period: usize,
+    input_script: VecDeque<Vec<f32>>,
+    input: Option<Slot<InputCallback>>,
+    output: Option<Slot<OutputCallback>>,
+    output_buffer: Vec<f32>,
+    captured: Vec<f32>,
+    /// Mono output frames on their way back to the input, in loopback mode.
+    loopback: Option<VecDeque<f32>>,
+}
+
+impl Default for Inner {
+    fn default() -> Self {
+        Self {
+            input_devices: Vec::new(),
+            output_devices: Vec::new(),
+            period: DEFAULT_PERIOD,
+            input_script: VecDeque::new(),
+            input: None,
+            output: None,
+            output_buffer: Vec::new(),
+            captured: Vec::new(),
+            loopback: None,
+        }
+    }
+}
+
+struct Slot<F> {
+    device: String,
+    data: F,
+    error: ErrorCallback,
+    config: cpal::StreamConfig,
+    state: Arc<StreamState>,
+}
+
+impl<F> Slot<F> {
+    fn is_playing(&self) -> bool {
+        self.state.playing.load(Ordering::Relaxed) && !self.is_closed()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.state.closed.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Default)]
+struct StreamState {
+    playing: AtomicBool,
+    closed: AtomicBool,
+}
+
+struct MockStream(Arc<StreamState>);
+
+impl AudioStream for MockStream {
+    fn play(&self) -> anyhow::Result<()> {
+        self.0.playing.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn pause(&self) -> anyhow::Result<()> {
+        self.0.playing.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl Drop for MockStream {
+    fn drop(&mut self) {
+        self.0.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Adds an input device. The first one added is the default.
+    pub fn with_input_device(self, device: MockDevice) -> Self {
+        self.add_input_device(device);
+        self
+    }
+
+    /// Adds an output device. The first one added is the default.
+    pub fn with_output_device(self, device: MockDevice) -> Self {
+        self.add_output_device(device);
+        self
+    }
+
+    /// Plugs in an input device.
+    pub fn add_input_device(&self, device: MockDevice) {
+        self.lock().input_devices.push(device);
+    }
+
+    /// Plugs in an output device.
+    pub fn add_output_device(&self, device: MockDevice) {
+        self.lock().output_devices.push(device);
+    }
+
+    /// Unplugs every device with this name. Open streams keep running until the route reacts to
+    /// [`MockBackend::fail`].
+    pub fn remove_device(&self, name: &str) {
+        let mut inner = self.lock();
+        inner.input_devices.retain(|x| x.name != name);
+        inner.output_devices.retain(|x| x.name != name);
+    }
+
+    /// The device name of the open input stream, if any.
+    pub fn input_stream_device(&self) -> Option<String> {
+        let inner = self.lock();
+        let slot = inner.input.as_ref()?;
+        (!slot.is_closed()).then(|| slot.device.clone())
+    }
+
+    /// The device name of the open output stream, if any.
+    pub fn output_stream_device(&self) -> Option<String> {
+        let inner = self.lock();
+        let slot = inner.output.as_ref()?;
+        (!slot.is_closed()).then(|| slot.device.clone())
+    }
+
+    /// Output block size in frames, used when the stream asks for `BufferSize::Default`.
+    pub fn with_period(self, frames: usize) -> Self {
+        self.lock().period = frames;
+        self
+    }
+
+    /// Feeds every output block back to the input stream, `frames` frames later, after the
+    /// output callback of each cycle. The output channels are averaged and the result is copied
+    /// to every input channel; the two devices must run at the same rate.
+    pub fn with_loopback(self, frames: usize) -> Self {
+        self.lock().loopback = Some(std::iter::repeat_n(0.0, frames).collect());
+        self
+    }
+
+    /// Queues an interleaved buffer to be delivered to the input stream on a later cycle.
+    pub fn push_input(&self, samples: impl Into<Vec<f32>>) {
+        self.lock().input_script.push_back(samples.into());
+    }
+
+    /// Queues `samples` split into buffers of `frames` frames of the default input device.
+    pub fn push_input_blocks(&self, samples: &[f32], frames: usize) {
+        let mut inner = self.lock();
+        let channels = inner
+            .input_devices
+            .first()
+            .map(|x| x.config.channels as usize)
+            .unwrap_or(1);
+        for block in samples.chunks(frames * channels) {
+            inner.input_script.push_back(block.to_vec());
+        }
+    }
+
+    /// Number of scripted input buffers not yet delivered.
+    pub fn pending_input(&self) -> usize {
+        self.lock().input_script.len()
+    }
+
+    /// Delivers the next scripted input buffer and captures one output block.
+    pub fn run_cycle(&self) {
+        self.run_input();
+        let mut inner = self.lock();
+        let inner = &mut *inner;
+        if let Some(output) = inner.output.as_mut().filter(|x| x.is_playing()) {
+            let frames = match output.config.buffer_size {
+                cpal::BufferSize::Fixed(frames) => frames as usize,
+                cpal::BufferSize::Default => inner.period,
+            };
+            // The buffer is reused between cycles, like a driver's would be, so frames the route
+            // leaves untouched keep their previous contents.
+            inner
+                .output_buffer
+                .resize(frames * output.config.channels as usize, 0.0);
+            (output.data)(&mut inner.output_buffer);
+            inner.captured.extend_from_slice(&inner.output_buffer);
+            if let Some(loopback) = &mut inner.loopback {
+                let channels = output.config.channels as usize;
+                loopback.extend(
+                    inner
+                        .output_buffer
+                        .chunks(channels)
+                        .map(|x| x.iter().sum::<f32>() / channels as f32),
+                );
+                let frames: Vec<f32> = loopback.drain(..frames).collect();
+                if let Some(input) = inner.input.as_mut().filter(|x| x.is_playing()) {
+                    let channels = input.config.channels as usize;
+                    let samples: Vec<f32> = frames
+                        .into_iter()
+                        .flat_map(|x| std::iter::repeat_n(x, channels))
+                        .collect();
+                    (input.data)(&samples);
+                }
+            }
+        }
+    }
+
+    /// Runs only the input callback with the next scripted buffer, as when the input stream
+    /// catches up after a late callback.
+    pub fn run_input(&self) {
+        let mut inner = self.lock();
+        let inner = &mut *inner;
+        if let Some(input) = inner.input.as_mut().filter(|x| x.is_playing()) {
+            if let Some(samples) = inner.input_script.pop_front() {
+                (input.data)(&samples);
+            }
+        }
+    }
+
+    pub fn run(&self, cycles: usize) {
+        for _ in 0..cycles {
+            self.run_cycle();
+        }
+    }
+
+    /// Everything written by the output stream so far, interleaved.
+    pub fn captured_output(&self) -> Vec<f32> {
+        self.lock().captured.clone()
+    }
+
+    /// Returns and clears the captured output.
+    pub fn take_output(&self) -> Vec<f32> {
+        std::mem::take(&mut self.lock().captured)
+    }
+
+    /// Reports `err` on every open stream, as a driver would.
+    pub fn fail(&self, err: cpal::StreamError) {
+        let mut inner = self.lock();
+        let inner = &mut *inner;
+        if let Some(input) = inner.input.as_mut().filter(|x| !x.is_closed()) {
+            (input.error)(clone_error(&err));
+        }
+        if let Some(output) = inner.output.as_mut().filter(|x| !x.is_closed()) {
+            (output.error)(clone_error(&err));
+        }
+    }
+}
+
+fn clone_error(err: &cpal::StreamError) -> cpal::StreamError {
+    match err {
+        cpal::StreamError::DeviceNotAvailable => cpal::StreamError::DeviceNotAvailable,
+        cpal::StreamError::BackendSpecific { err } => {
+            cpal::StreamError::BackendSpecific { err: err.clone() }
+        }
+    }
+}
+
+impl Backend for MockBackend {
+    type Device = MockDevice;
+
+    fn input_devices(&self) -> anyhow::Result<Vec<Self::Device>> {
+        Ok(self.lock().input_devices.clone())
+    }
+
+    fn output_devices(&self) -> anyhow::Result<Vec<Self::Device>> {
+        Ok(self.lock().output_devices.clone())
+    }
+
+    fn default_input_device(&self) -> Option<Self::Device> {
+        self.lock().input_devices.first().cloned()
+    }
+
+    fn default_output_device(&self) -> Option<Self::Device> {
+        self.lock().output_devices.first().cloned()
+    }
+
+    fn device_name(&self, device: &Self::Device) -> anyhow::Result<String> {
+        Ok(device.name.clone())
+    }
+
+    fn supported_input_configs(
+        &self,
+        device: &Self::Device,
+    ) -> anyhow::Result<Vec<cpal::SupportedStreamConfigRange>> {
+        Ok(device.supported.clone())
+    }
+
+    fn supported_output_configs(
+        &self,
+        device: &Self::Device,
+    ) -> anyhow::Result<Vec<cpal::SupportedStreamConfigRange>> {
+        Ok(device.supported.clone())
+    }
+
+    fn default_input_config(
+        &self,
+        device: &Self::Device,
+    ) -> anyhow::Result<cpal::SupportedStreamConfig> {
+        Ok(device.default_config())
+    }
+
+    fn default_output_config(
+        &self,
+        device: &Self::Device,
+    ) -> anyhow::Result<cpal::SupportedStreamConfig> {
+        Ok(device.default_config())
+    }
+
+    fn build_input_stream(
+        &self,
+        device: &Self::Device,
+        config: &cpal::StreamConfig,
+        format: cpal::SampleFormat,
+        data: InputCallback,
+        error: ErrorCallback,
+    ) -> anyhow::Result<Box<dyn AudioStream>> {
+        let mut inner = self.lock();
+        if !inner.input_devices.contains(device) {
+            anyhow::bail!("input device '{}' is not available", device.name);
+        }
+        device.check_format(format)?;
+        let data = with_sample_type!(format, T => device_input::<T>(data, config));
+        let state = Arc::new(StreamState::default());
+        inner.input = Some(Slot {
+            device: device.name.clone(),
+            data,
+            error,
+            config: config.clone(),
+            state: Arc::clone(&state),
+        });
+        Ok(Box::new(MockStream(state)))
+    }
+
+    fn build_output_stream(
+        &self,
+        device: &Self::Device,
+        config: &cpal::StreamConfig,
+        format: cpal::SampleFormat,
+        data: OutputCallback,
+        error: ErrorCallback,
+    ) -> anyhow::Result<Box<dyn AudioStream>> {
+        let mut inner = self.lock();
+        if !inner.output_devices.contains(device) {
+            anyhow::bail!("output device '{}' is not available", device.name);
+        }
+        device.check_format(format)?;
+        let data = with_sample_type!(format, T => device_output::<T>(data, config, format));
+        let state = Arc::new(StreamState::default());
+        inner.output = Some(Slot {
+            device: device.name.clone(),
+            data,
+            error,
+            config: config.clone(),
+            state: Arc::clone(&state),
+        });
+        Ok(Box::new(MockStream(state)))
+    }
+}
+
+/// Runs `data` behind the same conversion a device capturing `T` samples gets, with the scripted
+/// `f32` input converted to `T` first.
+fn device_input<T>(data: InputCallback, config: &cpal::StreamConfig) -> InputCallback
+where
+    T: SizedSample + FromSample<f32> + Send + 'static,
+    f32: FromSample<T>,
+{
+    let mut data = convert_input::<T>(data, config);
+    let mut samples = Vec::new();
+    Box::new(move |input: &[f32]| {
+        samples.clear();
+        samples.extend(input.iter().map(|&x| T::from_sample(x)));
+        data(&samples);
+    })
+}
+
+/// Runs `data` behind the same conversion a device playing `T` samples gets, and converts what
+/// it plays back to `f32` for the captured output.
+fn device_output<T>(
+    data: OutputCallback,
+    config: &cpal::StreamConfig,
+    format: cpal::SampleFormat,
+) -> OutputCallback
+where
+    T: SizedSample + FromSample<f32> + Send + 'static,
+    f32: FromSample<T>,
+{
+    let mut data = convert_output::<T>(data, config, format);
+    let mut samples = Vec::new();
+    Box::new(move |output: &mut [f32]| {
+        samples.resize(output.len(), T::EQUILIBRIUM);
+        data(&mut samples);
+        for (out, &x) in output.iter_mut().zip(&samples) {
+            *out = f32::from_sample(x);
+        }
+    })
+}
@@ -0,0 +1,76 @@
+mod cpal_host;
+mod format;
+pub mod mock;
+
+pub use cpal_host::CpalBackend;
+pub use mock::MockBackend;
+
+/// Receives interleaved `f32` samples captured by an input stream.
+pub type InputCallback = Box<dyn FnMut(&[f32]) + Send + 'static>;
+/// Fills an interleaved `f32` buffer for an output stream.
+pub type OutputCallback = Box<dyn FnMut(&mut [f32]) + Send + 'static>;
+/// Receives errors reported by a running stream.
+pub type ErrorCallback = Box<dyn FnMut(cpal::StreamError) + Send + 'static>;
+
+/// The device and stream operations a [`crate::Route`] needs from an audio host.
+pub trait Backend {
+    type Device;
+
+    fn input_devices(&self) -> anyhow::Result<Vec<Self::Device>>;
+
+    fn output_devices(&self) -> anyhow::Result<Vec<Self::Device>>;
+
+    fn default_input_device(&self) -> Option<Self::Device>;
+
+    fn default_output_device(&self) -> Option<Self::Device>;
+
+    fn device_name(&self, device: &Self::Device) -> anyhow::Result<String>;
+
+    fn supported_input_configs(
+        &self,
+        device: &Self::Device,
+    ) -> anyhow::Result<Vec<cpal::SupportedStreamConfigRange>>;
+
+    fn supported_output_configs(
+        &self,
+        device: &Self::Device,
+    ) -> anyhow::Result<Vec<cpal::SupportedStreamConfigRange>>;
+
+    fn default_input_config(
+        &self,
+        device: &Self::Device,
+    ) -> anyhow::Result<cpal::SupportedStreamConfig>;
+
+    fn default_output_config(
+        &self,
+        device: &Self::Device,
+    ) -> anyhow::Result<cpal::SupportedStreamConfig>;
+
+    /// Opens an input stream in `format`, whose samples are converted to `f32` for `data`.
+    fn build_input_stream(
+        &self,
+        device: &Self::Device,
+        config: &cpal::StreamConfig,
+        format: cpal::SampleFormat,
+        data: InputCallback,
+        error: ErrorCallback,
+    ) -> anyhow::Result<Box<dyn AudioStream>>;
+
+    /// Opens an output stream in `format`; the `f32` samples written by `data` are converted,
+    /// with dither for 8 to 24-bit integer formats.
+    fn build_output_stream(
+        &self,
+        device: &Self::Device,
+        config: &cpal::StreamConfig,
+        format: cpal::SampleFormat,
+        data: OutputCallback,
+        error: ErrorCallback,
+    ) -> anyhow::Result<Box<dyn AudioStream>>;
+}
+
+/// A built stream. Dropping it closes the stream.
+pub trait AudioStream {
+    fn play(&self) -> anyhow::Result<()>;
+
+    fn pause(&self) -> anyhow::Result<()>;
+}
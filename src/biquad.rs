@@ -0,0 +1,140 @@
+//! Second-order IIR filter sections with the coefficients of the RBJ audio EQ cookbook.
+
+use std::f64::consts::PI;
+
+/// Normalised coefficients of a biquad, with `a0` divided out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Coefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Coefficients {
+    /// A second-order highpass at `frequency` Hz with quality `q`.
+    pub(crate) fn highpass(sample_rate: u32, frequency: f32, q: f32) -> Self {
+        let (cos, alpha) = Self::prewarp(sample_rate, frequency, q);
+        Self::normalise(
+            (1.0 + cos) / 2.0,
+            -(1.0 + cos),
+            (1.0 + cos) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos,
+            1.0 - alpha,
+        )
+    }
+
+    /// A second-order lowpass at `frequency` Hz with quality `q`.
+    pub(crate) fn lowpass(sample_rate: u32, frequency: f32, q: f32) -> Self {
+        let (cos, alpha) = Self::prewarp(sample_rate, frequency, q);
+        Self::normalise(
+            (1.0 - cos) / 2.0,
+            1.0 - cos,
+            (1.0 - cos) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos,
+            1.0 - alpha,
+        )
+    }
+
+    /// A notch at `frequency` Hz, `q` wide.
+    pub(crate) fn notch(sample_rate: u32, frequency: f32, q: f32) -> Self {
+        let (cos, alpha) = Self::prewarp(sample_rate, frequency, q);
+        Self::normalise(1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha)
+    }
+
+    /// A bell boosting or cutting by `gain_db` around `frequency` Hz, `q` wide.
+    pub(crate) fn peaking(sample_rate: u32, frequency: f32, gain_db: f32, q: f32) -> Self {
+        let (cos, alpha) = Self::prewarp(sample_rate, frequency, q);
+        let a = 10_f64.powf(gain_db as f64 / 40.0);
+        Self::normalise(
+            1.0 + alpha * a,
+            -2.0 * cos,
+            1.0 - alpha * a,
+            1.0 + alpha / a,
+            -2.0 * cos,
+            1.0 - alpha / a,
+        )
+    }
+
+    /// A shelf boosting or cutting by `gain_db` below `frequency` Hz, with a slope set by `q`.
+    pub(crate) fn low_shelf(sample_rate: u32, frequency: f32, gain_db: f32, q: f32) -> Self {
+        let (cos, alpha) = Self::prewarp(sample_rate, frequency, q);
+        let a = 10_f64.powf(gain_db as f64 / 40.0);
+        let beta = 2.0 * a.sqrt() * alpha;
+        Self::normalise(
+            a * ((a + 1.0) - (a - 1.0) * cos + beta),
+            2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+            a * ((a + 1.0) - (a - 1.0) * cos - beta),
+            (a + 1.0) + (a - 1.0) * cos + beta,
+            -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+            (a + 1.0) + (a - 1.0) * cos - beta,
+        )
+    }
+
+    /// A shelf boosting or cutting by `gain_db` above `frequency` Hz, with a slope set by `q`.
+    pub(crate) fn high_shelf(sample_rate: u32, frequency: f32, gain_db: f32, q: f32) -> Self {
+        let (cos, alpha) = Self::prewarp(sample_rate, frequency, q);
+        let a = 10_f64.powf(gain_db as f64 / 40.0);
+        let beta = 2.0 * a.sqrt() * alpha;
+        Self::normalise(
+            a * ((a + 1.0) + (a - 1.0) * cos + beta),
+            -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+            a * ((a + 1.0) + (a - 1.0) * cos - beta),
+            (a + 1.0) - (a - 1.0) * cos + beta,
+            2.0 * ((a - 1.0) - (a + 1.0) * cos),
+            (a + 1.0) - (a - 1.0) * cos - beta,
+        )
+    }
+
+    /// The cosine and the bandwidth term of the cookbook for a centre or corner frequency, kept
+    /// below Nyquist.
+    fn prewarp(sample_rate: u32, frequency: f32, q: f32) -> (f64, f64) {
+        let nyquist = sample_rate as f64 / 2.0;
+        let frequency = (frequency as f64).clamp(1.0, nyquist * 0.99);
+        let omega = 2.0 * PI * frequency / sample_rate as f64;
+        (omega.cos(), omega.sin() / (2.0 * q.max(0.01) as f64))
+    }
+
+    fn normalise(b0: f64, b1: f64, b2: f64, a0: f64, a1: f64, a2: f64) -> Self {
+        Self {
+            b0: (b0 / a0) as f32,
+            b1: (b1 / a0) as f32,
+            b2: (b2 / a0) as f32,
+            a1: (a1 / a0) as f32,
+            a2: (a2 / a0) as f32,
+        }
+    }
+}
+
+/// One biquad section run on every channel of interleaved blocks, in transposed direct form II.
+pub(crate) struct Biquad {
+    coefficients: Coefficients,
+    /// Two state variables per channel.
+    state: Vec<[f32; 2]>,
+}
+
+impl Biquad {
+    pub(crate) fn new(coefficients: Coefficients, channels: usize) -> Self {
+        Self {
+            coefficients,
+            state: vec![[0.0; 2]; channels],
+        }
+    }
+
+    /// Filters one sample of `channel`.
+    pub(crate) fn tick(&mut self, channel: usize, x: f32) -> f32 {
+        let c = &self.coefficients;
+        let s = &mut self.state[channel];
+        let y = c.b0 * x + s[0];
+        s[0] = c.b1 * x - c.a1 * y + s[1];
+        s[1] = c.b2 * x - c.a2 * y;
+        y
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.state.iter_mut().for_each(|x| *x = [0.0; 2]);
+    }
+}
@@ -0,0 +1,155 @@
+//! A feed-forward compressor that evens out the level of the monitored voice.
+
+use crate::{gain::db_to_linear, processor::Processor};
+use std::time::Duration;
+
+/// Level assigned to digital silence by the detector, in dBFS.
+const SILENCE_DB: f32 = -120.0;
+
+/// The static curve and timing of a [`Compressor`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressorSettings {
+    /// Level in dBFS above which the signal is compressed.
+    pub threshold_db: f32,
+    /// Input level change in dB above the threshold per dB of output level change, at least 1.
+    pub ratio: f32,
+    /// Width in dB of the soft knee centred on the threshold; 0 is a hard knee.
+    pub knee_db: f32,
+    /// Time constant of the gain reduction when the level rises.
+    pub attack: Duration,
+    /// Time constant of the gain recovery when the level falls.
+    pub release: Duration,
+    /// Gain in dB applied after the compression to bring the level back up.
+    pub makeup_db: f32,
+    /// Delay of the signal behind the detector, so the gain is already down when a peak passes.
+    /// Adds to the latency of the route.
+    pub lookahead: Duration,
+}
+
+impl Default for CompressorSettings {
+    fn default() -> Self {
+        Self {
+            threshold_db: -24.0,
+            ratio: 4.0,
+            knee_db: 6.0,
+            attack: Duration::from_millis(5),
+            release: Duration::from_millis(100),
+            makeup_db: 0.0,
+            lookahead: Duration::ZERO,
+        }
+    }
+}
+
+impl CompressorSettings {
+    /// The gain in dB of the static curve for a detector level in dBFS.
+    fn gain_db(&self, level: f32) -> f32 {
+        let ratio = self.ratio.max(1.0);
+        let knee = self.knee_db.max(0.0);
+        let over = level - self.threshold_db;
+        let compressed = if knee > 0.0 && (2.0 * over).abs() <= knee {
+            level + (1.0 / ratio - 1.0) * (over + knee / 2.0).powi(2) / (2.0 * knee)
+        } else if over <= 0.0 {
+            level
+        } else {
+            self.threshold_db + over / ratio
+        };
+        compressed - level
+    }
+}
+
+/// Reduces the gain of the signal above a threshold by a ratio, with one detector for all
+/// channels so the stereo image stays in place.
+pub struct Compressor {
+    settings: CompressorSettings,
+    channels: usize,
+    attack: f32,
+    release: f32,
+    makeup: f32,
+    /// Smoothed gain reduction in dB, at most 0.
+    reduction: f32,
+    /// Interleaved lookahead delay line, with `position` at the oldest frame.
+    delay: Vec<f32>,
+    position: usize,
+}
+
+impl Compressor {
+    /// A compressor with no gain reduction yet. Its timing is set up by [`Processor::prepare`].
+    pub fn new(settings: CompressorSettings) -> Self {
+        Self {
+            settings,
+            channels: 1,
+            attack: 0.0,
+            release: 0.0,
+            makeup: 1.0,
+            reduction: 0.0,
+            delay: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// The current gain reduction in dB, at most 0.
+    pub fn reduction_db(&self) -> f32 {
+        self.reduction
+    }
+}
+
+impl Processor for Compressor {
+    fn prepare(&mut self, sample_rate: u32, channels: u16) {
+        let coefficient = |time: Duration| {
+            let frames = time.as_secs_f64() * sample_rate as f64;
+            if frames > 0.0 {
+                (-1.0 / frames).exp() as f32
+            } else {
+                0.0
+            }
+        };
+        self.channels = channels as usize;
+        self.attack = coefficient(self.settings.attack);
+        self.release = coefficient(self.settings.release);
+        self.makeup = db_to_linear(self.settings.makeup_db);
+        let lookahead =
+            (self.settings.lookahead.as_secs_f64() * sample_rate as f64).round() as usize;
+        self.delay = vec![0.0; lookahead * self.channels];
+        self.reset();
+    }
+
+    fn process(&mut self, block: &mut [f32]) {
+        for frame in block.chunks_mut(self.channels) {
+            let peak = frame.iter().fold(0.0_f32, |peak, x| peak.max(x.abs()));
+            let level = if peak > 0.0 {
+                (20.0 * peak.log10()).max(SILENCE_DB)
+            } else {
+                SILENCE_DB
+            };
+            let target = self.settings.gain_db(level);
+            let coefficient = if target < self.reduction {
+                self.attack
+            } else {
+                self.release
+            };
+            self.reduction = target + (self.reduction - target) * coefficient;
+            let gain = db_to_linear(self.reduction) * self.makeup;
+            if self.delay.is_empty() {
+                frame.iter_mut().for_each(|x| *x *= gain);
+            } else {
+                let delayed = &mut self.delay[self.position..self.position + self.channels];
+                for (x, old) in frame.iter_mut().zip(delayed) {
+                    let current = *x;
+                    *x = *old * gain;
+                    *old = current;
+                }
+                self.position = (self.position + self.channels) % self.delay.len();
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.reduction = 0.0;
+        self.delay.iter_mut().for_each(|x| *x = 0.0);
+        self.position = 0;
+    }
+
+    fn latency(&self) -> usize {
+        self.delay.len() / self.channels
+    }
+}
@@ -0,0 +1,236 @@
+//! Defined output when the buffer between the callbacks runs empty, and defined loss when it
+//! runs full.
+
+use std::{fmt, str::FromStr};
+
+/// Length of the fade out to silence and of the crossfade back to the real signal, in seconds.
+const FADE_SECONDS: f64 = 0.005;
+/// Time a repeated or continued signal takes to decay to silence, in seconds.
+const DECAY_SECONDS: f64 = 0.06;
+/// Shortest and longest pitch period searched for by [`Concealment::Continuation`], in seconds:
+/// 400 Hz down to 50 Hz.
+const MIN_PITCH_SECONDS: f64 = 0.0025;
+const MAX_PITCH_SECONDS: f64 = 0.02;
+/// Length of the most recent audio matched against earlier audio, in seconds.
+const MATCH_SECONDS: f64 = 0.005;
+
+/// What the output plays for the frames the input did not deliver in time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Concealment {
+    /// Fades the last sample out to silence within a few milliseconds.
+    #[default]
+    Silence,
+    /// Repeats the last output block, decaying to silence within a few tens of milliseconds.
+    Repeat,
+    /// Finds the pitch period of the recent signal by waveform similarity and continues it period
+    /// by period, decaying like [`Concealment::Repeat`]. Voiced sound continues without the
+    /// periodic clicks of a repeated block.
+    Continuation,
+}
+
+impl FromStr for Concealment {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "silence" => Ok(Self::Silence),
+            "repeat" => Ok(Self::Repeat),
+            "continuation" => Ok(Self::Continuation),
+            _ => anyhow::bail!(
+                "unknown concealment '{}', expected silence, repeat or continuation",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Concealment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Silence => write!(f, "silence"),
+            Self::Repeat => write!(f, "repeat"),
+            Self::Continuation => write!(f, "continuation"),
+        }
+    }
+}
+
+/// Which audio is lost when the input delivers more than the buffer can hold.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Keeps the buffered audio and drops the part of the input block that does not fit. The
+    /// delay stays at its highest until the output catches up.
+    #[default]
+    DropNewest,
+    /// Keeps the newest audio of the input block that fits and lets the output discard the audio
+    /// buffered before it, down to the latency target, so the delay returns to the target at once.
+    DropOldest,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop-newest" => Ok(Self::DropNewest),
+            "drop-oldest" => Ok(Self::DropOldest),
+            _ => anyhow::bail!(
+                "unknown overflow policy '{}', expected drop-newest or drop-oldest",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for OverflowPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DropNewest => write!(f, "drop-newest"),
+            Self::DropOldest => write!(f, "drop-oldest"),
+        }
+    }
+}
+
+/// Fills the frames missing from an interleaved output block and crossfades back into the real
+/// signal once it returns. Allocates only in [`Concealer::new`].
+pub(crate) struct Concealer {
+    strategy: Concealment,
+    channels: usize,
+    /// The most recent real output, interleaved, oldest first.
+    history: Vec<f32>,
+    fade: usize,
+    decay: usize,
+    min_pitch: usize,
+    max_pitch: usize,
+    window: usize,
+    concealing: bool,
+    /// Length of the history tail that is played in a loop, in samples.
+    period: usize,
+    position: usize,
+    /// Samples played since the concealment started.
+    elapsed: usize,
+}
+
+impl Concealer {
+    /// `max_block` is the largest output block in frames that [`Concealment::Repeat`] should be
+    /// able to repeat.
+    pub(crate) fn new(
+        strategy: Concealment,
+        sample_rate: u32,
+        channels: u16,
+        max_block: usize,
+    ) -> Self {
+        let frames = |seconds: f64| ((seconds * sample_rate as f64).round() as usize).max(1);
+        let min_pitch = frames(MIN_PITCH_SECONDS);
+        let max_pitch = frames(MAX_PITCH_SECONDS).max(min_pitch);
+        let window = frames(MATCH_SECONDS);
+        let channels = channels as usize;
+        Self {
+            strategy,
+            channels,
+            history: vec![0.0; max_block.max(max_pitch + window) * channels],
+            fade: frames(FADE_SECONDS),
+            decay: frames(DECAY_SECONDS),
+            min_pitch,
+            max_pitch,
+            window,
+            concealing: false,
+            period: channels,
+            position: 0,
+            elapsed: 0,
+        }
+    }
+
+    /// Replaces the frames of `block` after the first `produced` with the concealment and returns
+    /// whether it had to conceal. The first frames of a block that ends a concealment are
+    /// crossfaded from it.
+    pub(crate) fn process(&mut self, block: &mut [f32], produced: usize) -> bool {
+        let produced = produced * self.channels;
+        if produced > 0 && self.concealing {
+            let fade = self.fade.min(produced / self.channels);
+            for (i, frame) in block[..fade * self.channels]
+                .chunks_exact_mut(self.channels)
+                .enumerate()
+            {
+                let t = (i + 1) as f32 / (fade + 1) as f32;
+                for sample in frame {
+                    *sample = *sample * t + self.next() * (1.0 - t);
+                }
+            }
+            self.concealing = false;
+        }
+        self.remember(&block[..produced]);
+        if produced == block.len() {
+            return false;
+        }
+        if !self.concealing {
+            self.start(block.len());
+        }
+        for sample in &mut block[produced..] {
+            *sample = self.next();
+        }
+        true
+    }
+
+    fn remember(&mut self, samples: &[f32]) {
+        let len = self.history.len();
+        if samples.len() >= len {
+            self.history
+                .copy_from_slice(&samples[samples.len() - len..]);
+        } else {
+            self.history.copy_within(samples.len().., 0);
+            self.history[len - samples.len()..].copy_from_slice(samples);
+        }
+    }
+
+    fn start(&mut self, block: usize) {
+        self.period = match self.strategy {
+            Concealment::Silence => self.channels,
+            Concealment::Repeat => block.clamp(self.channels, self.history.len()),
+            Concealment::Continuation => self.pitch_period() * self.channels,
+        };
+        self.position = 0;
+        self.elapsed = 0;
+        self.concealing = true;
+    }
+
+    /// The lag in frames at which the most recent audio best matches the audio before it.
+    fn pitch_period(&self) -> usize {
+        let len = self.history.len();
+        let window = self.window * self.channels;
+        let recent = &self.history[len - window..];
+        let recent_energy: f64 = recent.iter().map(|&x| x as f64 * x as f64).sum();
+        let mut best = (self.max_pitch, 0.0);
+        for lag in self.min_pitch..=self.max_pitch {
+            let offset = lag * self.channels;
+            let earlier = &self.history[len - window - offset..len - offset];
+            let (mut dot, mut energy) = (0.0, 0.0);
+            for (&x, &y) in recent.iter().zip(earlier) {
+                dot += x as f64 * y as f64;
+                energy += y as f64 * y as f64;
+            }
+            if energy > 0.0 && recent_energy > 0.0 {
+                let similarity = dot / (recent_energy * energy).sqrt();
+                if similarity > best.1 {
+                    best = (lag, similarity);
+                }
+            }
+        }
+        best.0
+    }
+
+    fn next(&mut self) -> f32 {
+        let length = match self.strategy {
+            Concealment::Silence => self.fade,
+            Concealment::Repeat | Concealment::Continuation => self.decay,
+        };
+        let gain = 1.0 - (self.elapsed / self.channels) as f32 / length as f32;
+        if gain <= 0.0 {
+            return 0.0;
+        }
+        let len = self.history.len();
+        let sample = self.history[len - self.period + self.position];
+        self.position = (self.position + 1) % self.period;
+        self.elapsed += 1;
+        sample * gain
+    }
+}
@@ -0,0 +1,249 @@
+//! Negotiation of the stream config of each device from what the user asked for and what the
+//! device supports.
+
+use crate::{backend::Backend, route::InvalidSettings};
+use std::fmt;
+use tracing::{info, warn};
+
+/// The stream config requested for one device. Fields left `None` keep the device default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StreamRequest {
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
+    pub sample_format: Option<cpal::SampleFormat>,
+    /// Fixed callback buffer size in frames, instead of the driver's choice.
+    pub buffer_frames: Option<u32>,
+}
+
+impl StreamRequest {
+    /// Whether the sample rate, channel count and format are left at the device default.
+    fn is_default_config(&self) -> bool {
+        self.sample_rate.is_none() && self.channels.is_none() && self.sample_format.is_none()
+    }
+}
+
+/// The config a stream is opened with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NegotiatedConfig {
+    pub config: cpal::StreamConfig,
+    pub sample_format: cpal::SampleFormat,
+}
+
+impl NegotiatedConfig {
+    /// The fixed buffer size in frames, unless the driver picks it.
+    pub fn buffer_frames(&self) -> Option<u32> {
+        match self.config.buffer_size {
+            cpal::BufferSize::Fixed(frames) => Some(frames),
+            cpal::BufferSize::Default => None,
+        }
+    }
+}
+
+impl fmt::Display for NegotiatedConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} Hz, {} channel{}, {}, ",
+            self.config.sample_rate.0,
+            self.config.channels,
+            if self.config.channels == 1 { "" } else { "s" },
+            self.sample_format
+        )?;
+        match self.buffer_frames() {
+            Some(frames) => write!(f, "{} frame buffer", frames),
+            None => write!(f, "buffer size chosen by the driver"),
+        }
+    }
+}
+
+/// Parses a sample format by its cpal name, e.g. `i16` or `f32`.
+pub fn parse_sample_format(s: &str) -> anyhow::Result<cpal::SampleFormat> {
+    match s {
+        "i8" => Ok(cpal::SampleFormat::I8),
+        "i16" => Ok(cpal::SampleFormat::I16),
+        "i32" => Ok(cpal::SampleFormat::I32),
+        "i64" => Ok(cpal::SampleFormat::I64),
+        "u8" => Ok(cpal::SampleFormat::U8),
+        "u16" => Ok(cpal::SampleFormat::U16),
+        "u32" => Ok(cpal::SampleFormat::U32),
+        "u64" => Ok(cpal::SampleFormat::U64),
+        "f32" => Ok(cpal::SampleFormat::F32),
+        "f64" => Ok(cpal::SampleFormat::F64),
+        _ => anyhow::bail!(
+            "unknown sample format '{}', expected i8, i16, i32, i64, u8, u16, u32, u64, f32 or f64",
+            s
+        ),
+    }
+}
+
+/// Picks the supported config nearest to `request`, filling the fields it leaves open from
+/// `default`.
+///
+/// Without a requested rate, channel count or format the default config is kept as it is.
+/// Otherwise every supported range is scored by how far it is from the requested fields first,
+/// in the order sample rate, channel count, sample format, and then by how far it is from the
+/// default in the fields that were not requested. Every requested field that could not be met is
+/// logged as a warning, except the buffer size: one outside the range of the chosen config is an
+/// error.
+pub fn negotiate(
+    supported: &[cpal::SupportedStreamConfigRange],
+    default: &cpal::SupportedStreamConfig,
+    request: &StreamRequest,
+) -> anyhow::Result<NegotiatedConfig> {
+    let sample_rate = request.sample_rate.unwrap_or(default.sample_rate().0);
+    let channels = request.channels.unwrap_or(default.channels());
+    let sample_format = request.sample_format.unwrap_or(default.sample_format());
+    let (mut config, buffer_size) = if request.is_default_config() {
+        let buffer_size = supported
+            .iter()
+            .find(|x| {
+                x.channels() == channels
+                    && x.sample_format() == sample_format
+                    && (x.min_sample_rate()..=x.max_sample_rate()).contains(&default.sample_rate())
+            })
+            .map(|x| *x.buffer_size())
+            .unwrap_or(cpal::SupportedBufferSize::Unknown);
+        (
+            NegotiatedConfig {
+                config: default.config(),
+                sample_format,
+            },
+            buffer_size,
+        )
+    } else {
+        let nearest = supported
+            .iter()
+            .map(|x| {
+                let rate = sample_rate.clamp(x.min_sample_rate().0, x.max_sample_rate().0);
+                (x, rate)
+            })
+            .min_by_key(|&(x, rate)| {
+                let misses = [
+                    rate.abs_diff(sample_rate),
+                    x.channels().abs_diff(channels) as u32,
+                    (x.sample_format() != sample_format) as u32,
+                ];
+                let requested = [
+                    request.sample_rate.is_some(),
+                    request.channels.is_some(),
+                    request.sample_format.is_some(),
+                ];
+                let score = |wanted: bool| {
+                    let mut score = [0; 3];
+                    for ((score, miss), requested) in score.iter_mut().zip(misses).zip(requested) {
+                        if requested == wanted {
+                            *score = miss;
+                        }
+                    }
+                    score
+                };
+                (score(true), score(false))
+            });
+        match nearest {
+            Some((x, rate)) => (
+                NegotiatedConfig {
+                    config: cpal::StreamConfig {
+                        channels: x.channels(),
+                        sample_rate: cpal::SampleRate(rate),
+                        buffer_size: cpal::BufferSize::Default,
+                    },
+                    sample_format: x.sample_format(),
+                },
+                *x.buffer_size(),
+            ),
+            None => {
+                warn!("the device does not report its configs, trying the requested one anyway");
+                (
+                    NegotiatedConfig {
+                        config: cpal::StreamConfig {
+                            channels,
+                            sample_rate: cpal::SampleRate(sample_rate),
+                            buffer_size: cpal::BufferSize::Default,
+                        },
+                        sample_format,
+                    },
+                    cpal::SupportedBufferSize::Unknown,
+                )
+            }
+        }
+    };
+    if let Some(rate) = request
+        .sample_rate
+        .filter(|&x| x != config.config.sample_rate.0)
+    {
+        warn!(
+            "{} Hz is not supported, using {} Hz",
+            rate, config.config.sample_rate.0
+        );
+    }
+    if let Some(channels) = request.channels.filter(|&x| x != config.config.channels) {
+        warn!(
+            "{} channels are not supported, using {}",
+            channels, config.config.channels
+        );
+    }
+    if let Some(format) = request.sample_format.filter(|&x| x != config.sample_format) {
+        warn!(
+            "sample format {} is not supported, using {}",
+            format, config.sample_format
+        );
+    }
+    if let Some(frames) = request.buffer_frames {
+        match buffer_size {
+            cpal::SupportedBufferSize::Range { min, max } if !(min..=max).contains(&frames) => {
+                return Err(InvalidSettings(format!(
+                    "{} frames is not supported; the device supports {}-{} frames",
+                    frames, min, max
+                ))
+                .into());
+            }
+            cpal::SupportedBufferSize::Range { .. } => {}
+            cpal::SupportedBufferSize::Unknown => {
+                warn!(
+                    "the device does not report its buffer sizes, trying {} frames anyway",
+                    frames
+                );
+            }
+        }
+        config.config.buffer_size = cpal::BufferSize::Fixed(frames);
+    }
+    Ok(config)
+}
+
+/// Negotiates the config of an input device and logs the result.
+pub(crate) fn negotiate_input<B: Backend>(
+    backend: &B,
+    device: &B::Device,
+    request: &StreamRequest,
+) -> anyhow::Result<NegotiatedConfig> {
+    let config = negotiate(
+        &backend.supported_input_configs(device)?,
+        &backend.default_input_config(device)?,
+        request,
+    )?;
+    info!(
+        "Input device '{}' runs at {}",
+        backend.device_name(device)?,
+        config
+    );
+    Ok(config)
+}
+
+/// Negotiates the config of an output device and logs the result.
+pub(crate) fn negotiate_output<B: Backend>(
+    backend: &B,
+    device: &B::Device,
+    request: &StreamRequest,
+) -> anyhow::Result<NegotiatedConfig> {
+    let config = negotiate(
+        &backend.supported_output_configs(device)?,
+        &backend.default_output_config(device)?,
+        request,
+    )?;
+    info!(
+        "Output device '{}' runs at {}",
+        backend.device_name(device)?,
+        config
+    );
+    Ok(config)
+}
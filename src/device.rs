@@ -0,0 +1,207 @@
+use crate::{backend::Backend, route::InvalidSettings};
+use anyhow::Context;
+use std::{fmt, str::FromStr};
+
+/// ALSA PCM name prefixes, in order of preference, used to pick one device when a card selector
+/// matches several PCMs of the same card.
+const CARD_PCM_PREFERENCE: [&str; 4] = ["plughw:", "hw:", "sysdefault:", "front:"];
+
+/// How a device is picked out of a backend's device list.
+///
+/// Parsed from a string:
+/// - `default` selects the backend default device;
+/// - a number selects the device at that position of the list shown by `--list-devices`;
+/// - `re:<regex>` selects the device whose name matches the regular expression;
+/// - `card:<card>[,<device>]` selects an ALSA device by its card identifier, e.g. `card:Headset,0`,
+///   which stays the same across reboots unlike the card number;
+/// - anything else selects the device with exactly that name, or else the device whose name
+///   contains it, ignoring case.
+#[derive(Debug, Clone)]
+pub enum DeviceSelector {
+    Default,
+    Index(usize),
+    Name(String),
+    Regex(regex::Regex),
+    Card { card: String, device: Option<u32> },
+}
+
+impl FromStr for DeviceSelector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "default" {
+            Ok(Self::Default)
+        } else if let Ok(index) = s.parse() {
+            Ok(Self::Index(index))
+        } else if let Some(pattern) = s.strip_prefix("re:") {
+            let regex = regex::Regex::new(pattern)
+                .with_context(|| format!("invalid device regex '{}'", pattern))?;
+            Ok(Self::Regex(regex))
+        } else if let Some(card) = s.strip_prefix("card:") {
+            let (card, device) = match card.split_once(',') {
+                Some((card, device)) => (
+                    card,
+                    Some(
+                        device
+                            .parse()
+                            .with_context(|| format!("invalid ALSA device number '{}'", device))?,
+                    ),
+                ),
+                None => (card, None),
+            };
+            Ok(Self::Card {
+                card: card.to_string(),
+                device,
+            })
+        } else {
+            Ok(Self::Name(s.to_string()))
+        }
+    }
+}
+
+impl fmt::Display for DeviceSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::Index(index) => write!(f, "{}", index),
+            Self::Name(name) => write!(f, "{}", name),
+            Self::Regex(regex) => write!(f, "re:{}", regex),
+            Self::Card {
+                card,
+                device: Some(device),
+            } => write!(f, "card:{},{}", card, device),
+            Self::Card { card, device: None } => write!(f, "card:{}", card),
+        }
+    }
+}
+
+impl DeviceSelector {
+    /// Returns the position in `names` of the one device this selector picks. Not meaningful for
+    /// [`DeviceSelector::Default`], which depends on the backend rather than the names.
+    pub fn select(&self, names: &[String]) -> anyhow::Result<usize> {
+        let candidates: Vec<usize> = match self {
+            Self::Default => anyhow::bail!("the default device is not selected by name"),
+            Self::Index(index) if *index < names.len() => vec![*index],
+            Self::Index(index) => anyhow::bail!(
+                "there is no device {}; {} devices are available",
+                index,
+                names.len()
+            ),
+            Self::Name(name) => match names.iter().position(|x| x == name) {
+                Some(index) => vec![index],
+                None => {
+                    let needle = name.to_lowercase();
+                    matching(names, |x| x.to_lowercase().contains(&needle))
+                }
+            },
+            Self::Regex(regex) => matching(names, |x| regex.is_match(x)),
+            Self::Card { card, device } => {
+                let candidates = matching(names, |x| {
+                    alsa_card(x).is_some_and(|(c, d)| {
+                        c.eq_ignore_ascii_case(card) && device.is_none_or(|y| d == Some(y))
+                    })
+                });
+                prefer_pcm(names, candidates)
+            }
+        };
+        match candidates[..] {
+            [index] => Ok(index),
+            [] => anyhow::bail!("no device matches '{}'", self),
+            _ => anyhow::bail!(
+                "'{}' is ambiguous; it matches {}",
+                self,
+                candidates
+                    .iter()
+                    .map(|&x| format!("{}. {}", x, names[x]))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+fn matching(names: &[String], predicate: impl Fn(&str) -> bool) -> Vec<usize> {
+    names
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| predicate(x))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Parses the `CARD=` and `DEV=` parameters of an ALSA PCM name like `hw:CARD=Headset,DEV=0`.
+fn alsa_card(name: &str) -> Option<(&str, Option<u32>)> {
+    let (_, params) = name.split_once(':')?;
+    let mut card = None;
+    let mut device = None;
+    for param in params.split(',') {
+        match param.split_once('=') {
+            Some(("CARD", value)) => card = Some(value),
+            Some(("DEV", value)) => device = value.parse().ok(),
+            _ => {}
+        }
+    }
+    Some((card?, device))
+}
+
+fn prefer_pcm(names: &[String], candidates: Vec<usize>) -> Vec<usize> {
+    if candidates.len() < 2 {
+        return candidates;
+    }
+    CARD_PCM_PREFERENCE
+        .iter()
+        .map(|prefix| {
+            candidates
+                .iter()
+                .copied()
+                .filter(|&x| names[x].starts_with(prefix))
+                .collect::<Vec<_>>()
+        })
+        .find(|x| x.len() == 1)
+        .unwrap_or(candidates)
+}
+
+/// Finds an input device by a [`DeviceSelector`] string.
+pub fn find_input_device<B: Backend>(selector: &str, backend: &B) -> anyhow::Result<B::Device> {
+    match parse_selector(selector)? {
+        DeviceSelector::Default => backend
+            .default_input_device()
+            .context("there is no default input device"),
+        selector => select_device(&selector, backend.input_devices()?, backend),
+    }
+}
+
+/// Finds an output device by a [`DeviceSelector`] string.
+pub fn find_output_device<B: Backend>(selector: &str, backend: &B) -> anyhow::Result<B::Device> {
+    match parse_selector(selector)? {
+        DeviceSelector::Default => backend
+            .default_output_device()
+            .context("there is no default output device"),
+        selector => select_device(&selector, backend.output_devices()?, backend),
+    }
+}
+
+/// Parses a selector; a malformed one is an [`InvalidSettings`] error, since it fails the same
+/// way however often the devices are looked up.
+fn parse_selector(selector: &str) -> anyhow::Result<DeviceSelector> {
+    selector.parse().context(InvalidSettings(format!(
+        "invalid device selector '{}'",
+        selector
+    )))
+}
+
+fn select_device<B: Backend>(
+    selector: &DeviceSelector,
+    devices: Vec<B::Device>,
+    backend: &B,
+) -> anyhow::Result<B::Device> {
+    let names: Vec<String> = devices
+        .iter()
+        .map(|x| backend.device_name(x).unwrap_or_default())
+        .collect();
+    let index = selector.select(&names)?;
+    devices
+        .into_iter()
+        .nth(index)
+        .context("device list changed while selecting")
+}
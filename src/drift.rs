@@ -0,0 +1,81 @@
+//! Keeps the ring buffer at its primed level when the input and output clocks drift apart.
+
+/// Time constant of the moving average of the buffer level, in seconds.
+const AVERAGE_SECONDS: f64 = 2.0;
+/// The level to hold is learned over this many seconds after start.
+const CALIBRATION_SECONDS: f64 = 4.0;
+/// An error of one second of buffered audio is corrected over this many seconds.
+const CORRECTION_SECONDS: f64 = 10.0;
+/// A constant error is integrated away over this many seconds.
+const INTEGRAL_SECONDS: f64 = 120.0;
+/// Largest ratio adjustment: 0.5%, far beyond the drift of any working sound card.
+pub(crate) const MAX_ADJUSTMENT: f64 = 0.005;
+
+/// The level observed in the output callback depends on how the input and output callbacks
+/// happen to interleave, not only on the priming, so the controller holds the average level it
+/// measured during calibration rather than the nominal one.
+pub(crate) struct DriftController {
+    /// Buffer level to hold, in samples, once calibration is over.
+    target: Option<f64>,
+    calibration_sum: f64,
+    calibration_time: f64,
+    /// Samples buffered per second of audio.
+    samples_per_second: f64,
+    output_rate: f64,
+    average: f64,
+    integral: f64,
+}
+
+impl DriftController {
+    pub(crate) fn new(samples_per_second: f64, output_rate: u32) -> Self {
+        Self {
+            target: None,
+            calibration_sum: 0.0,
+            calibration_time: 0.0,
+            samples_per_second,
+            output_rate: output_rate as f64,
+            average: 0.0,
+            integral: 0.0,
+        }
+    }
+
+    /// Feeds the current buffer level, observed before `frames` output frames are produced, and
+    /// returns the factor the resampling ratio should be scaled by.
+    pub(crate) fn update(&mut self, level: usize, frames: usize) -> f64 {
+        let dt = frames as f64 / self.output_rate;
+        let target = match self.target {
+            Some(target) => target,
+            None => {
+                self.calibration_sum += level as f64 * dt;
+                self.calibration_time += dt;
+                self.average = self.calibration_sum / self.calibration_time;
+                if self.calibration_time >= CALIBRATION_SECONDS {
+                    self.target = Some(self.average);
+                }
+                return 1.0;
+            }
+        };
+        let alpha = (dt / AVERAGE_SECONDS).min(1.0);
+        self.average += (level as f64 - self.average) * alpha;
+        let error = (self.average - target) / self.samples_per_second;
+        self.integral =
+            (self.integral + error * dt / INTEGRAL_SECONDS).clamp(-MAX_ADJUSTMENT, MAX_ADJUSTMENT);
+        1.0 + (error / CORRECTION_SECONDS + self.integral).clamp(-MAX_ADJUSTMENT, MAX_ADJUSTMENT)
+    }
+
+    /// Moves the level to hold by `delta` samples. A change during calibration restarts it.
+    pub(crate) fn shift_target(&mut self, delta: f64) {
+        match &mut self.target {
+            Some(target) => *target += delta,
+            None => {
+                self.calibration_sum = 0.0;
+                self.calibration_time = 0.0;
+            }
+        }
+    }
+
+    /// The averaged buffer level, in samples.
+    pub(crate) fn level(&self) -> f64 {
+        self.average
+    }
+}
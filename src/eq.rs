@@ -0,0 +1,240 @@
+//! A parametric equalizer that shapes the tone of the monitored signal.
+
+use crate::{
+    biquad::{Biquad, Coefficients},
+    processor::Processor,
+};
+use anyhow::Context;
+use std::{fmt, str::FromStr};
+
+/// Quality used when a band does not give one: a Butterworth response for the filters and
+/// shelves, about two octaves wide for bells and notches.
+pub const DEFAULT_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// The response of one band of an [`Equalizer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BandKind {
+    /// Boosts or cuts around the frequency.
+    Peak,
+    /// Boosts or cuts everything below the frequency.
+    LowShelf,
+    /// Boosts or cuts everything above the frequency.
+    HighShelf,
+    /// Removes everything below the frequency at 12 dB per octave.
+    HighPass,
+    /// Removes everything above the frequency at 12 dB per octave.
+    LowPass,
+    /// Removes a narrow band around the frequency, such as mains hum.
+    Notch,
+}
+
+impl BandKind {
+    /// Whether the band boosts or cuts by a gain.
+    fn has_gain(self) -> bool {
+        matches!(self, Self::Peak | Self::LowShelf | Self::HighShelf)
+    }
+}
+
+impl FromStr for BandKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "peak" => Ok(Self::Peak),
+            "lowshelf" => Ok(Self::LowShelf),
+            "highshelf" => Ok(Self::HighShelf),
+            "highpass" => Ok(Self::HighPass),
+            "lowpass" => Ok(Self::LowPass),
+            "notch" => Ok(Self::Notch),
+            _ => anyhow::bail!(
+                "unknown band '{}', expected peak, lowshelf, highshelf, highpass, lowpass or notch",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for BandKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Peak => write!(f, "peak"),
+            Self::LowShelf => write!(f, "lowshelf"),
+            Self::HighShelf => write!(f, "highshelf"),
+            Self::HighPass => write!(f, "highpass"),
+            Self::LowPass => write!(f, "lowpass"),
+            Self::Notch => write!(f, "notch"),
+        }
+    }
+}
+
+/// One biquad section of an [`Equalizer`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Band {
+    pub kind: BandKind,
+    /// Centre or corner frequency in Hz.
+    pub frequency: f32,
+    /// Boost or cut in dB; ignored by the pass filters and the notch.
+    pub gain_db: f32,
+    pub q: f32,
+}
+
+impl Band {
+    fn coefficients(&self, sample_rate: u32) -> Coefficients {
+        let (frequency, gain, q) = (self.frequency, self.gain_db, self.q);
+        match self.kind {
+            BandKind::Peak => Coefficients::peaking(sample_rate, frequency, gain, q),
+            BandKind::LowShelf => Coefficients::low_shelf(sample_rate, frequency, gain, q),
+            BandKind::HighShelf => Coefficients::high_shelf(sample_rate, frequency, gain, q),
+            BandKind::HighPass => Coefficients::highpass(sample_rate, frequency, q),
+            BandKind::LowPass => Coefficients::lowpass(sample_rate, frequency, q),
+            BandKind::Notch => Coefficients::notch(sample_rate, frequency, q),
+        }
+    }
+}
+
+/// The bands of an [`Equalizer`], applied in order.
+///
+/// Parsed from a comma-separated list of `<kind>:<Hz>:<gain dB>[:<Q>]` for `peak`, `lowshelf`
+/// and `highshelf`, and `<kind>:<Hz>[:<Q>]` for `highpass`, `lowpass` and `notch`, e.g.
+/// `highpass:100,peak:250:-4:1.5,highshelf:5000:3` thins out a boomy headset mic.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EqSettings {
+    bands: Vec<Band>,
+}
+
+impl EqSettings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a band.
+    pub fn band(mut self, kind: BandKind, frequency: f32, gain_db: f32, q: f32) -> Self {
+        self.bands.push(Band {
+            kind,
+            frequency,
+            gain_db,
+            q,
+        });
+        self
+    }
+
+    pub fn bands(&self) -> &[Band] {
+        &self.bands
+    }
+}
+
+impl FromStr for EqSettings {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let number = |x: &str, what: &str, band: &str| -> anyhow::Result<f32> {
+            let value: f32 = x
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid {} '{}' in '{}'", what, x, band))?;
+            if !value.is_finite() {
+                anyhow::bail!("invalid {} '{}' in '{}'", what, x, band);
+            }
+            Ok(value)
+        };
+        let mut settings = Self::new();
+        for band in s.split(',') {
+            let mut parts = band.split(':');
+            let kind: BandKind = parts.next().unwrap_or_default().trim().parse()?;
+            let expected = if kind.has_gain() {
+                "<kind>:<Hz>:<gain dB>[:<Q>]"
+            } else {
+                "<kind>:<Hz>[:<Q>]"
+            };
+            let Some(frequency) = parts.next() else {
+                anyhow::bail!("invalid band '{}', expected {}", band, expected);
+            };
+            let frequency = number(frequency, "frequency", band)?;
+            if frequency <= 0.0 {
+                anyhow::bail!("invalid frequency in '{}', expected a positive value", band);
+            }
+            let gain_db = if kind.has_gain() {
+                let Some(gain) = parts.next() else {
+                    anyhow::bail!("invalid band '{}', expected {}", band, expected);
+                };
+                number(gain, "gain", band)?
+            } else {
+                0.0
+            };
+            let q = match parts.next() {
+                Some(q) => number(q, "Q", band)?,
+                None => DEFAULT_Q,
+            };
+            if q <= 0.0 {
+                anyhow::bail!("invalid Q in '{}', expected a positive value", band);
+            }
+            if parts.next().is_some() {
+                anyhow::bail!("invalid band '{}', expected {}", band, expected);
+            }
+            settings = settings.band(kind, frequency, gain_db, q);
+        }
+        Ok(settings)
+    }
+}
+
+impl fmt::Display for EqSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, band) in self.bands.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}:{}", band.kind, band.frequency)?;
+            if band.kind.has_gain() {
+                write!(f, ":{}", band.gain_db)?;
+            }
+            if band.q != DEFAULT_Q {
+                write!(f, ":{}", band.q)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A cascade of biquad sections, one per band, run on every channel. The coefficients are
+/// computed for the sample rate of the output stream whenever the chain is prepared for it.
+pub struct Equalizer {
+    settings: EqSettings,
+    channels: usize,
+    sections: Vec<Biquad>,
+}
+
+impl Equalizer {
+    pub fn new(settings: EqSettings) -> Self {
+        Self {
+            settings,
+            channels: 1,
+            sections: Vec::new(),
+        }
+    }
+}
+
+impl Processor for Equalizer {
+    fn prepare(&mut self, sample_rate: u32, channels: u16) {
+        self.channels = channels as usize;
+        self.sections = self
+            .settings
+            .bands
+            .iter()
+            .map(|band| Biquad::new(band.coefficients(sample_rate), self.channels))
+            .collect();
+    }
+
+    fn process(&mut self, block: &mut [f32]) {
+        for frame in block.chunks_mut(self.channels) {
+            for (channel, x) in frame.iter_mut().enumerate() {
+                for section in &mut self.sections {
+                    *x = section.tick(channel, *x);
+                }
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.sections.iter_mut().for_each(Biquad::reset);
+    }
+}
@@ -0,0 +1,59 @@
+//! Equal-power gain ramps for starting, stopping, muting and unmuting the output without clicks.
+
+use std::f32::consts::FRAC_PI_2;
+
+/// Ramps the gain of interleaved blocks between silence and full level. The gain follows a
+/// quarter sine, so the power of the faded signal plus that of its complement stays constant.
+pub(crate) struct Fader {
+    /// Progress of the ramp from 0.0, closed, to 1.0, open.
+    position: f32,
+    rise: f32,
+    fall: f32,
+    open: bool,
+    /// Frames to stay closed before opening.
+    hold: usize,
+}
+
+impl Fader {
+    /// A closed fader that opens over `rise` frames and closes over `fall` frames.
+    pub(crate) fn new(rise: usize, fall: usize) -> Self {
+        Self {
+            position: 0.0,
+            rise: 1.0 / rise.max(1) as f32,
+            fall: 1.0 / fall.max(1) as f32,
+            open: false,
+            hold: 0,
+        }
+    }
+
+    /// Keeps the fader closed for the next `frames` frames even when it is set to open.
+    pub(crate) fn hold(&mut self, frames: usize) {
+        self.hold = frames;
+    }
+
+    pub(crate) fn set_open(&mut self, open: bool) {
+        self.open = open;
+    }
+
+    /// Whether the fader is set to close and has finished closing.
+    pub(crate) fn is_closed(&self) -> bool {
+        !self.open && self.position <= 0.0
+    }
+
+    pub(crate) fn apply(&mut self, data: &mut [f32], channels: usize) {
+        if self.open && self.hold == 0 && self.position >= 1.0 {
+            return;
+        }
+        for frame in data.chunks_mut(channels) {
+            if self.hold > 0 {
+                self.hold -= 1;
+            } else if self.open {
+                self.position = (self.position + self.rise).min(1.0);
+            } else {
+                self.position = (self.position - self.fall).max(0.0);
+            }
+            let gain = (self.position * FRAC_PI_2).sin();
+            frame.iter_mut().for_each(|x| *x *= gain);
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! The level of the monitored signal, changed without zipper noise.
+
+/// Highest gain accepted by [`crate::Sidetone::gain_db`] and [`crate::Route::set_gain_db`].
+pub const MAX_GAIN_DB: f32 = 30.0;
+/// Time constant of the gain smoothing, in seconds.
+const SMOOTHING_SECONDS: f64 = 0.02;
+/// Distance to the target below which the smoothed gain snaps to it.
+const SNAP: f32 = 1e-5;
+
+/// Converts a level in dB to a linear amplitude factor.
+pub(crate) fn db_to_linear(db: f32) -> f32 {
+    10_f32.powf(db / 20.0)
+}
+
+/// Clamps a gain in dB to [`MAX_GAIN_DB`] and converts it to a linear factor. NaN is silence.
+pub(crate) fn gain_to_linear(db: f32) -> f32 {
+    if db.is_nan() {
+        return 0.0;
+    }
+    db_to_linear(db.min(MAX_GAIN_DB))
+}
+
+/// Applies a gain to interleaved blocks, following changes of the target per frame with a
+/// one-pole lowpass so they never step.
+pub(crate) struct Gain {
+    current: f32,
+    /// Fraction of the remaining distance covered per frame.
+    coefficient: f32,
+}
+
+impl Gain {
+    /// A gain that starts at `initial` without a ramp.
+    pub(crate) fn new(initial: f32, sample_rate: u32) -> Self {
+        Self {
+            current: initial,
+            coefficient: (1.0 - (-1.0 / (SMOOTHING_SECONDS * sample_rate as f64)).exp()) as f32,
+        }
+    }
+
+    pub(crate) fn apply(&mut self, data: &mut [f32], channels: usize, target: f32) {
+        if self.current == target {
+            if target != 1.0 {
+                data.iter_mut().for_each(|x| *x *= target);
+            }
+            return;
+        }
+        for frame in data.chunks_mut(channels) {
+            self.current += (target - self.current) * self.coefficient;
+            if (target - self.current).abs() < SNAP {
+                self.current = target;
+            }
+            let gain = self.current;
+            frame.iter_mut().for_each(|x| *x *= gain);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! A noise gate that silences the monitored signal between words.
+
+use crate::{
+    biquad::{Biquad, Coefficients},
+    gain::db_to_linear,
+    processor::Processor,
+};
+use std::time::Duration;
+
+/// Release time of the level detector, in seconds. Short enough to follow the gaps between
+/// syllables, long enough not to ripple with low voices.
+const DETECTOR_RELEASE_SECONDS: f64 = 0.01;
+/// Quality of the sidechain highpass: Butterworth.
+const SIDECHAIN_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// When and how far a [`NoiseGate`] opens and closes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GateSettings {
+    /// Level in dBFS the sidechain has to reach for the gate to open.
+    pub open_threshold_db: f32,
+    /// Level in dBFS the sidechain has to fall below for the gate to start closing. Kept at or
+    /// below the open threshold, so a level between the two neither opens nor closes the gate.
+    pub close_threshold_db: f32,
+    /// Time the gain takes to rise from the floor to unity once the gate opens.
+    pub attack: Duration,
+    /// Time the gate stays open after the level fell below the close threshold.
+    pub hold: Duration,
+    /// Time the gain takes to fall from unity to the floor once the hold has run out.
+    pub release: Duration,
+    /// Gain in dB of the closed gate, e.g. -40 to turn noise down rather than off.
+    pub range_db: f32,
+    /// Cutoff in Hz of the highpass applied to the level detector only, so rumble and handling
+    /// noise do not open the gate; `None` detects the unfiltered signal.
+    pub sidechain_highpass: Option<f32>,
+}
+
+impl Default for GateSettings {
+    fn default() -> Self {
+        Self {
+            open_threshold_db: -45.0,
+            close_threshold_db: -50.0,
+            attack: Duration::from_millis(1),
+            hold: Duration::from_millis(100),
+            release: Duration::from_millis(150),
+            range_db: -60.0,
+            sidechain_highpass: Some(120.0),
+        }
+    }
+}
+
+/// Attenuates the signal while its level stays below a threshold. All channels share one
+/// detector, so the gate opens and closes on every channel at once.
+pub struct NoiseGate {
+    settings: GateSettings,
+    channels: usize,
+    open_threshold: f32,
+    close_threshold: f32,
+    floor: f32,
+    /// Gain change per frame while opening and closing.
+    attack_step: f32,
+    release_step: f32,
+    hold_frames: usize,
+    detector_release: f32,
+    sidechain: Option<Biquad>,
+    envelope: f32,
+    open: bool,
+    /// Frames left before a gate below the close threshold starts closing.
+    hold: usize,
+    gain: f32,
+}
+
+impl NoiseGate {
+    /// A closed gate. Its timing is set up by [`Processor::prepare`].
+    pub fn new(settings: GateSettings) -> Self {
+        Self {
+            settings,
+            channels: 1,
+            open_threshold: 0.0,
+            close_threshold: 0.0,
+            floor: 0.0,
+            attack_step: 1.0,
+            release_step: 1.0,
+            hold_frames: 0,
+            detector_release: 0.0,
+            sidechain: None,
+            envelope: 0.0,
+            open: false,
+            hold: 0,
+            gain: 0.0,
+        }
+    }
+
+    /// Whether the gate is open or holding open.
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// The level of the sidechain, tracked per frame.
+    fn detect(&mut self, frame: &[f32]) -> f32 {
+        let mut peak = 0.0_f32;
+        for (channel, &x) in frame.iter().enumerate() {
+            let x = match &mut self.sidechain {
+                Some(filter) => filter.tick(channel, x),
+                None => x,
+            };
+            peak = peak.max(x.abs());
+        }
+        self.envelope = if peak > self.envelope {
+            peak
+        } else {
+            self.envelope * self.detector_release
+        };
+        self.envelope
+    }
+}
+
+impl Processor for NoiseGate {
+    fn prepare(&mut self, sample_rate: u32, channels: u16) {
+        let s = &self.settings;
+        let frames = |time: Duration| (time.as_secs_f64() * sample_rate as f64).round() as usize;
+        self.channels = channels as usize;
+        self.open_threshold = db_to_linear(s.open_threshold_db);
+        self.close_threshold = db_to_linear(s.close_threshold_db.min(s.open_threshold_db));
+        self.floor = db_to_linear(s.range_db.min(0.0));
+        self.attack_step = (1.0 - self.floor) / frames(s.attack).max(1) as f32;
+        self.release_step = (1.0 - self.floor) / frames(s.release).max(1) as f32;
+        self.hold_frames = frames(s.hold);
+        self.detector_release =
+            (-1.0 / (DETECTOR_RELEASE_SECONDS * sample_rate as f64)).exp() as f32;
+        self.sidechain = s.sidechain_highpass.map(|frequency| {
+            Biquad::new(
+                Coefficients::highpass(sample_rate, frequency, SIDECHAIN_Q),
+                self.channels,
+            )
+        });
+        self.reset();
+    }
+
+    fn process(&mut self, block: &mut [f32]) {
+        for frame in block.chunks_mut(self.channels) {
+            let level = self.detect(frame);
+            if level >= self.open_threshold {
+                self.open = true;
+                self.hold = self.hold_frames;
+            } else if self.open && level >= self.close_threshold {
+                // Between the thresholds an open gate stays open.
+                self.hold = self.hold_frames;
+            } else if self.open && self.hold > 0 {
+                self.hold -= 1;
+            } else {
+                self.open = false;
+            }
+            self.gain = if self.open {
+                (self.gain + self.attack_step).min(1.0)
+            } else {
+                (self.gain - self.release_step).max(self.floor)
+            };
+            let gain = self.gain;
+            frame.iter_mut().for_each(|x| *x *= gain);
+        }
+    }
+
+    fn reset(&mut self) {
+        if let Some(filter) = &mut self.sidechain {
+            filter.reset();
+        }
+        self.envelope = 0.0;
+        self.open = false;
+        self.hold = 0;
+        self.gain = self.floor;
+    }
+}
@@ -0,0 +1,27 @@
+pub mod backend;
+mod biquad;
+pub mod compressor;
+pub mod conceal;
+pub mod config;
+pub mod device;
+mod drift;
+pub mod eq;
+mod fade;
+pub mod gain;
+pub mod gate;
+mod limiter;
+pub mod list;
+pub mod measure;
+pub mod mix;
+pub mod processor;
+pub mod render;
+pub mod resample;
+mod ring;
+pub mod route;
+pub mod settings;
+pub mod supervisor;
+mod tuner;
+
+pub use backend::{Backend, CpalBackend, MockBackend};
+pub use processor::{Chain, Processor};
+pub use route::{Route, RouteStats, Sidetone};
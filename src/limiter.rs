@@ -0,0 +1,160 @@
+//! The brickwall limiter that keeps the output below its ceiling, between samples too.
+
+use std::f64::consts::PI;
+
+/// Oversampling factor of the true-peak detector.
+const OVERSAMPLING: usize = 4;
+/// Taps of each interpolation phase. The detector lags the signal by half of them.
+const TAPS: usize = 8;
+/// Time the gain takes to come down ahead of a peak, in seconds.
+const LOOKAHEAD_SECONDS: f64 = 0.001;
+/// Time constant of the gain recovery after a peak, in seconds.
+const RELEASE_SECONDS: f64 = 0.05;
+
+/// A true-peak limiter with lookahead. The signal is delayed by the lookahead and the detector
+/// lag; the gain ahead of every over is lowered by a moving minimum and smoothed by a moving
+/// average of the same length, so it is already down when the over arrives. A final clamp catches
+/// rounding errors.
+pub(crate) struct Limiter {
+    ceiling: f32,
+    channels: usize,
+    /// `OVERSAMPLING - 1` phases of `TAPS` interpolation coefficients.
+    phases: Vec<[f32; TAPS]>,
+    /// The last `TAPS` input samples per channel, interleaved, oldest first.
+    history: Vec<f32>,
+    release: f32,
+    /// Required gain, recovering at the release rate.
+    envelope: f32,
+    /// Moving minimum and average windows over the last `lookahead + 1` frames.
+    minimum: Vec<f32>,
+    average: Vec<f32>,
+    sum: f64,
+    window: usize,
+    /// Interleaved audio delay line, with `position` at the oldest frame.
+    delay: Vec<f32>,
+    position: usize,
+    /// Frames since the last over, up to `release_frames`.
+    since_over: usize,
+    /// Overs closer together than this many frames count as one event.
+    release_frames: usize,
+    /// Consecutive silent input frames, up to the length of the delay.
+    silent_frames: usize,
+}
+
+impl Limiter {
+    /// A limiter at `ceiling`, a linear amplitude.
+    pub(crate) fn new(ceiling: f32, sample_rate: u32, channels: usize) -> Self {
+        let lookahead = ((LOOKAHEAD_SECONDS * sample_rate as f64).round() as usize).max(1);
+        let delay_frames = lookahead + TAPS / 2;
+        let release_frames = (RELEASE_SECONDS * sample_rate as f64).round() as usize;
+        Self {
+            ceiling,
+            channels,
+            phases: (1..OVERSAMPLING).map(interpolation_phase).collect(),
+            history: vec![0.0; TAPS * channels],
+            release: (1.0 - (-1.0 / (RELEASE_SECONDS * sample_rate as f64)).exp()) as f32,
+            envelope: 1.0,
+            minimum: vec![1.0; lookahead + 1],
+            average: vec![1.0; lookahead + 1],
+            sum: (lookahead + 1) as f64,
+            window: 0,
+            delay: vec![0.0; delay_frames * channels],
+            position: 0,
+            since_over: release_frames,
+            release_frames,
+            silent_frames: delay_frames,
+        }
+    }
+
+    /// The delay the limiter adds, in frames.
+    pub(crate) fn latency(&self) -> usize {
+        self.delay.len() / self.channels
+    }
+
+    /// Whether everything the limiter still holds is silence.
+    pub(crate) fn is_silent(&self) -> bool {
+        self.silent_frames >= self.latency()
+    }
+
+    /// Limits an interleaved block in place and returns how many overs started in it. Overs
+    /// within the release time of the previous one continue its event.
+    pub(crate) fn process(&mut self, block: &mut [f32]) -> u64 {
+        let mut overs = 0;
+        for frame in block.chunks_exact_mut(self.channels) {
+            let peak = self.true_peak(frame);
+            let required = if peak > self.ceiling {
+                if self.since_over >= self.release_frames {
+                    overs += 1;
+                }
+                self.since_over = 0;
+                self.ceiling / peak
+            } else {
+                self.since_over = (self.since_over + 1).min(self.release_frames);
+                1.0
+            };
+            self.envelope = required.min(self.envelope + (1.0 - self.envelope) * self.release);
+            self.minimum[self.window] = self.envelope;
+            let minimum = self.minimum.iter().copied().fold(1.0_f32, f32::min);
+            self.sum += minimum as f64 - self.average[self.window] as f64;
+            self.average[self.window] = minimum;
+            self.window = (self.window + 1) % self.average.len();
+            let gain = (self.sum / self.average.len() as f64) as f32;
+
+            if frame.iter().all(|&x| x == 0.0) {
+                self.silent_frames = (self.silent_frames + 1).min(self.latency());
+            } else {
+                self.silent_frames = 0;
+            }
+            let delayed = &mut self.delay[self.position..self.position + self.channels];
+            for (x, old) in frame.iter_mut().zip(delayed) {
+                let current = *x;
+                *x = (*old * gain).clamp(-self.ceiling, self.ceiling);
+                *old = current;
+            }
+            self.position = (self.position + self.channels) % self.delay.len();
+        }
+        overs
+    }
+
+    /// The highest sample or interpolated intersample level of the frame that entered the
+    /// interpolation filters `TAPS / 2` frames ago.
+    fn true_peak(&mut self, frame: &[f32]) -> f32 {
+        self.history.copy_within(self.channels.., 0);
+        let len = self.history.len();
+        self.history[len - self.channels..].copy_from_slice(frame);
+        let mut peak = 0.0_f32;
+        for channel in 0..self.channels {
+            let sample = self.history[(TAPS / 2 - 1) * self.channels + channel];
+            peak = peak.max(sample.abs());
+            for phase in &self.phases {
+                let interpolated: f32 = phase
+                    .iter()
+                    .enumerate()
+                    .map(|(tap, h)| h * self.history[tap * self.channels + channel])
+                    .sum();
+                peak = peak.max(interpolated.abs());
+            }
+        }
+        peak
+    }
+}
+
+/// Hann-windowed sinc coefficients that interpolate `phase / OVERSAMPLING` of the way from the
+/// sample at `TAPS / 2 - 1` to the next one.
+fn interpolation_phase(phase: usize) -> [f32; TAPS] {
+    let offset = phase as f64 / OVERSAMPLING as f64;
+    let mut taps = [0.0_f64; TAPS];
+    for (tap, h) in taps.iter_mut().enumerate() {
+        let t = tap as f64 - (TAPS / 2 - 1) as f64 - offset;
+        let sinc = if t == 0.0 {
+            1.0
+        } else {
+            (PI * t).sin() / (PI * t)
+        };
+        let window = 0.5 + 0.5 * (PI * t / (TAPS / 2) as f64).cos();
+        *h = sinc * window;
+    }
+    // Unity gain at DC, so a steady level is detected as itself.
+    let sum: f64 = taps.iter().sum();
+    taps.map(|h| (h / sum) as f32)
+}
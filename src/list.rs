@@ -0,0 +1,176 @@
+use cpal::{
+    traits::{DeviceTrait, HostTrait},
+    SupportedBufferSize, SupportedStreamConfigRange,
+};
+use serde::Serialize;
+use std::fmt;
+use tracing::warn;
+
+/// Listed in place of the name of a device that fails to report one.
+const UNKNOWN_NAME: &str = "<unknown>";
+
+/// Everything a host reports about its devices.
+#[derive(Debug, Clone, Serialize)]
+pub struct HostReport {
+    pub name: String,
+    pub default: bool,
+    /// Set when the host or its device list could not be opened, e.g. a JACK server not running.
+    pub error: Option<String>,
+    pub inputs: Vec<DeviceReport>,
+    pub outputs: Vec<DeviceReport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceReport {
+    /// Position in the host's input or output device list.
+    pub index: usize,
+    pub name: String,
+    pub default: bool,
+    pub configs: Vec<ConfigReport>,
+}
+
+/// One supported stream configuration range of a device.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigReport {
+    pub channels: u16,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32,
+    /// Supported buffer sizes in frames, if the host can report them.
+    pub min_buffer_size: Option<u32>,
+    pub max_buffer_size: Option<u32>,
+    pub sample_format: String,
+}
+
+impl From<SupportedStreamConfigRange> for ConfigReport {
+    fn from(range: SupportedStreamConfigRange) -> Self {
+        let (min_buffer_size, max_buffer_size) = match *range.buffer_size() {
+            SupportedBufferSize::Range { min, max } => (Some(min), Some(max)),
+            SupportedBufferSize::Unknown => (None, None),
+        };
+        Self {
+            channels: range.channels(),
+            min_sample_rate: range.min_sample_rate().0,
+            max_sample_rate: range.max_sample_rate().0,
+            min_buffer_size,
+            max_buffer_size,
+            sample_format: range.sample_format().to_string(),
+        }
+    }
+}
+
+/// Collects every input and output device of every host compiled into this build.
+pub fn list_devices() -> Vec<HostReport> {
+    let default_host = cpal::default_host().id();
+    cpal::available_hosts()
+        .into_iter()
+        .map(|id| {
+            let mut report = HostReport {
+                name: id.name().to_string(),
+                default: id == default_host,
+                error: None,
+                inputs: Vec::new(),
+                outputs: Vec::new(),
+            };
+            match cpal::host_from_id(id) {
+                Ok(host) => {
+                    if let Err(err) = list_host_devices(&host, &mut report) {
+                        report.error = Some(err.to_string());
+                    }
+                }
+                Err(err) => report.error = Some(err.to_string()),
+            }
+            report
+        })
+        .collect()
+}
+
+fn list_host_devices(host: &cpal::Host, report: &mut HostReport) -> anyhow::Result<()> {
+    let default_input = host.default_input_device().and_then(|x| x.name().ok());
+    let default_output = host.default_output_device().and_then(|x| x.name().ok());
+    for (index, device) in host.input_devices()?.enumerate() {
+        let name = device_name(&device, index);
+        report.inputs.push(DeviceReport {
+            index,
+            default: default_input.as_deref() == Some(name.as_str()),
+            name,
+            configs: device
+                .supported_input_configs()
+                .map(|x| x.map(ConfigReport::from).collect())
+                .unwrap_or_default(),
+        });
+    }
+    for (index, device) in host.output_devices()?.enumerate() {
+        let name = device_name(&device, index);
+        report.outputs.push(DeviceReport {
+            index,
+            default: default_output.as_deref() == Some(name.as_str()),
+            name,
+            configs: device
+                .supported_output_configs()
+                .map(|x| x.map(ConfigReport::from).collect())
+                .unwrap_or_default(),
+        });
+    }
+    Ok(())
+}
+
+/// The name of the device, or a placeholder when it cannot be read, so one broken device does
+/// not hide the rest of the list.
+fn device_name(device: &cpal::Device, index: usize) -> String {
+    device.name().unwrap_or_else(|err| {
+        warn!("failed to read the name of device {}: {}", index, err);
+        String::from(UNKNOWN_NAME)
+    })
+}
+
+impl fmt::Display for HostReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if self.default {
+            write!(f, " (default host)")?;
+        }
+        writeln!(f)?;
+        if let Some(err) = &self.error {
+            return writeln!(f, "  unavailable: {}", err);
+        }
+        writeln!(f, "  Input devices:")?;
+        for device in &self.inputs {
+            write!(f, "{}", device)?;
+        }
+        writeln!(f, "  Output devices:")?;
+        for device in &self.outputs {
+            write!(f, "{}", device)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DeviceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "    {}. {}", self.index, self.name)?;
+        if self.default {
+            write!(f, " (default)")?;
+        }
+        writeln!(f)?;
+        for config in &self.configs {
+            writeln!(f, "         {}", config)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ConfigReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ch, ", self.channels)?;
+        if self.min_sample_rate == self.max_sample_rate {
+            write!(f, "{} Hz, ", self.min_sample_rate)?;
+        } else {
+            write!(f, "{}-{} Hz, ", self.min_sample_rate, self.max_sample_rate)?;
+        }
+        match (self.min_buffer_size, self.max_buffer_size) {
+            (Some(min), Some(max)) => write!(f, "buffer {}-{} frames, ", min, max)?,
+            _ => write!(f, "buffer size unknown, ")?,
+        }
+        write!(f, "{}", self.sample_format)
+    }
+}
@@ -1,26 +1,388 @@
 use anyhow::Context;
-use clap::Parser;
-use cpal::{
-    traits::{DeviceTrait, HostTrait, StreamTrait},
-    Device, Host,
+use clap::{Args, Parser, Subcommand};
+use sidetone::{
+    compressor::{Compressor, CompressorSettings},
+    conceal::{Concealment, OverflowPolicy},
+    config::{parse_sample_format, StreamRequest},
+    device::DeviceSelector,
+    eq::{EqSettings, Equalizer},
+    gate::{GateSettings, NoiseGate},
+    list::list_devices,
+    measure::measure_latency,
+    mix::RoutingMatrix,
+    render::render_file,
+    resample::Quality,
+    settings::SettingsFile,
+    supervisor::{RecoveryPolicy, Supervisor},
+    CpalBackend, Sidetone,
 };
-use std::sync::mpsc::sync_channel;
+use std::{path::PathBuf, time::Duration};
 use tracing::{debug, error, info, level_filters::LevelFilter};
 use tracing_subscriber::EnvFilter;
 
-const INITIAL_LATENCY: std::time::Duration = std::time::Duration::from_millis(1000);
-const BUFFER_SIZE: usize = 550;
-
 #[derive(Parser, Debug)]
 #[command(version, about = "sidetone", long_about = None)]
 struct Cli {
-    /// The input audio device to use
-    #[arg(short, long, value_name = "IN", default_value_t = String::from("default"))]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// The audio host to use, e.g. ALSA or JACK (see --list-devices)
+    #[arg(long, value_name = "HOST", default_value_t = String::from("default"))]
+    host: String,
+
+    /// Read the settings of --route and --eq from a JSON file, e.g. {"route": "3:1,5:2", "eq":
+    /// "highpass:100"}; options given on the command line take precedence
+    #[arg(long, value_name = "FILE", global = true)]
+    config: Option<PathBuf>,
+
+    /// The input audio device to use: a name, list index, name substring, re:<regex> or
+    /// card:<ALSA card>[,<device>]
+    #[arg(
+        short,
+        long,
+        value_name = "IN",
+        default_value_t = String::from("default"),
+        value_parser = device_selector
+    )]
     input_device: String,
 
-    /// The output audio device to use
-    #[arg(short, long, value_name = "OUT", default_value_t = String::from("default"))]
+    /// The output audio device to use, selected like the input device
+    #[arg(
+        short,
+        long,
+        value_name = "OUT",
+        default_value_t = String::from("default"),
+        value_parser = device_selector
+    )]
     output_device: String,
+
+    /// Delay between capturing and playing back a sample
+    #[arg(long, value_name = "MS", default_value_t = 50, global = true)]
+    latency_ms: u64,
+
+    /// Find the lowest glitch-free latency at runtime instead of using --latency-ms
+    #[arg(long)]
+    auto_latency: bool,
+
+    /// Upper bound for the automatically tuned latency
+    #[arg(
+        long,
+        value_name = "MS",
+        default_value_t = 500,
+        requires = "auto_latency"
+    )]
+    max_latency_ms: u64,
+
+    /// Fixed device buffer size in frames [default: chosen by the driver]
+    #[arg(long, value_name = "FRAMES", global = true)]
+    buffer_frames: Option<u32>,
+
+    /// Sample rate to open the input device at, or the nearest one it supports [default: the
+    /// device default]
+    #[arg(long, value_name = "HZ", global = true)]
+    input_rate: Option<u32>,
+
+    /// Channel count to open the input device with, or the nearest one it supports
+    #[arg(long, value_name = "N", global = true)]
+    input_channels: Option<u16>,
+
+    /// Sample format to open the input device with, e.g. i16 or f32, if it supports it
+    #[arg(long, value_name = "FORMAT", value_parser = parse_sample_format, global = true)]
+    input_format: Option<cpal::SampleFormat>,
+
+    /// Buffer size of the input device in frames, overriding --buffer-frames
+    #[arg(long, value_name = "FRAMES", global = true)]
+    input_buffer_frames: Option<u32>,
+
+    /// Sample rate to open the output device at, or the nearest one it supports [default: the
+    /// device default]
+    #[arg(long, value_name = "HZ", global = true)]
+    output_rate: Option<u32>,
+
+    /// Channel count to open the output device with, or the nearest one it supports
+    #[arg(long, value_name = "N", global = true)]
+    output_channels: Option<u16>,
+
+    /// Sample format to open the output device with, e.g. i16 or f32, if it supports it
+    #[arg(long, value_name = "FORMAT", value_parser = parse_sample_format, global = true)]
+    output_format: Option<cpal::SampleFormat>,
+
+    /// Buffer size of the output device in frames, overriding --buffer-frames
+    #[arg(long, value_name = "FRAMES", global = true)]
+    output_buffer_frames: Option<u32>,
+
+    /// Sample rate conversion quality when the devices run at different rates: fast, balanced or
+    /// high
+    #[arg(long, value_name = "QUALITY", default_value_t = Quality::default(), global = true)]
+    resample_quality: Quality,
+
+    /// Map input channels to output channels as <in>:<out>[:<gain dB>],... with channels counted
+    /// from 1, e.g. 3:1,5:2 [default: by channel position]
+    #[arg(long, value_name = "MATRIX", global = true)]
+    route: Option<RoutingMatrix>,
+
+    /// Level of the monitored signal in dB, up to +30
+    #[arg(
+        long,
+        value_name = "DB",
+        default_value_t = 0.0,
+        allow_negative_numbers = true,
+        global = true
+    )]
+    gain_db: f32,
+
+    /// Keep the output below this true-peak level with a brickwall limiter, whatever the gain
+    #[arg(
+        long,
+        value_name = "DBFS",
+        default_value_t = 0.0,
+        allow_negative_numbers = true,
+        global = true
+    )]
+    ceiling_db: f32,
+
+    /// Shape the monitored signal with EQ bands: <kind>:<Hz>:<gain dB>[:<Q>] for peak, lowshelf
+    /// and highshelf, <kind>:<Hz>[:<Q>] for highpass, lowpass and notch, e.g.
+    /// highpass:100,peak:250:-4:1.5
+    #[arg(long, value_name = "BANDS", global = true)]
+    eq: Option<EqSettings>,
+
+    /// What to play while the input falls behind: silence, repeat or continuation
+    #[arg(long, value_name = "STRATEGY", default_value_t = Concealment::default(), global = true)]
+    concealment: Concealment,
+
+    /// Which audio to drop when the output falls behind: drop-newest or drop-oldest
+    #[arg(long, value_name = "POLICY", default_value_t = OverflowPolicy::default(), global = true)]
+    overflow: OverflowPolicy,
+
+    /// Ramp up from silence over this long when the route starts or is rebuilt
+    #[arg(long, value_name = "MS", default_value_t = 10, global = true)]
+    fade_in_ms: u64,
+
+    /// Ramp down to silence over this long on shutdown
+    #[arg(long, value_name = "MS", default_value_t = 10)]
+    fade_out_ms: u64,
+
+    /// Stop right after the fade out on Ctrl-C instead of playing the buffered audio first
+    #[arg(long)]
+    no_drain: bool,
+
+    /// Play the input at its nominal rate instead of tracking the clock drift between the devices
+    #[arg(long)]
+    no_drift_compensation: bool,
+
+    /// List every host and device with their supported stream configurations, then exit
+    #[arg(short, long)]
+    list_devices: bool,
+
+    /// Print the device list as JSON
+    #[arg(long, requires = "list_devices")]
+    json: bool,
+
+    #[command(flatten)]
+    gate: GateArgs,
+
+    #[command(flatten)]
+    compressor: CompressorArgs,
+
+    #[command(flatten)]
+    recovery: RecoveryArgs,
+}
+
+#[derive(Args, Debug)]
+struct GateArgs {
+    /// Silence the monitored signal while it stays below the gate thresholds
+    #[arg(long, global = true)]
+    gate: bool,
+
+    /// Level the signal has to reach to open the gate
+    #[arg(
+        long,
+        value_name = "DBFS",
+        default_value_t = -45.0,
+        allow_negative_numbers = true,
+        global = true
+    )]
+    gate_open_db: f32,
+
+    /// Level the signal has to fall below to close the gate
+    #[arg(
+        long,
+        value_name = "DBFS",
+        default_value_t = -50.0,
+        allow_negative_numbers = true,
+        global = true
+    )]
+    gate_close_db: f32,
+
+    /// Time the gate takes to open
+    #[arg(long, value_name = "MS", default_value_t = 1.0, global = true)]
+    gate_attack_ms: f64,
+
+    /// Time the gate stays open after the signal fell below the close threshold
+    #[arg(long, value_name = "MS", default_value_t = 100.0, global = true)]
+    gate_hold_ms: f64,
+
+    /// Time the gate takes to close
+    #[arg(long, value_name = "MS", default_value_t = 150.0, global = true)]
+    gate_release_ms: f64,
+
+    /// Attenuation of the closed gate
+    #[arg(
+        long,
+        value_name = "DB",
+        default_value_t = -60.0,
+        allow_negative_numbers = true,
+        global = true
+    )]
+    gate_range_db: f32,
+
+    /// Cutoff of the highpass on the gate detector, so rumble does not open it; 0 turns it off
+    #[arg(long, value_name = "HZ", default_value_t = 120.0, global = true)]
+    gate_sidechain_hz: f32,
+}
+
+impl From<&GateArgs> for GateSettings {
+    fn from(args: &GateArgs) -> Self {
+        Self {
+            open_threshold_db: args.gate_open_db,
+            close_threshold_db: args.gate_close_db,
+            attack: Duration::from_secs_f64(args.gate_attack_ms / 1e3),
+            hold: Duration::from_secs_f64(args.gate_hold_ms / 1e3),
+            release: Duration::from_secs_f64(args.gate_release_ms / 1e3),
+            range_db: args.gate_range_db,
+            sidechain_highpass: (args.gate_sidechain_hz > 0.0).then_some(args.gate_sidechain_hz),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct CompressorArgs {
+    /// Even out the level of the monitored signal with a compressor
+    #[arg(long, global = true)]
+    compressor: bool,
+
+    /// Level above which the compressor reduces the gain
+    #[arg(
+        long,
+        value_name = "DBFS",
+        default_value_t = -24.0,
+        allow_negative_numbers = true,
+        global = true
+    )]
+    compressor_threshold_db: f32,
+
+    /// Input level change per dB of output level change above the threshold
+    #[arg(long, value_name = "RATIO", default_value_t = 4.0, global = true)]
+    compressor_ratio: f32,
+
+    /// Width of the soft knee around the threshold; 0 for a hard knee
+    #[arg(long, value_name = "DB", default_value_t = 6.0, global = true)]
+    compressor_knee_db: f32,
+
+    /// Time the compressor takes to reduce the gain
+    #[arg(long, value_name = "MS", default_value_t = 5.0, global = true)]
+    compressor_attack_ms: f64,
+
+    /// Time the compressor takes to recover the gain
+    #[arg(long, value_name = "MS", default_value_t = 100.0, global = true)]
+    compressor_release_ms: f64,
+
+    /// Gain applied after the compression
+    #[arg(
+        long,
+        value_name = "DB",
+        default_value_t = 0.0,
+        allow_negative_numbers = true,
+        global = true
+    )]
+    compressor_makeup_db: f32,
+
+    /// Delay the signal behind the detector to catch peaks, adding to the latency
+    #[arg(long, value_name = "MS", default_value_t = 0.0, global = true)]
+    compressor_lookahead_ms: f64,
+}
+
+impl From<&CompressorArgs> for CompressorSettings {
+    fn from(args: &CompressorArgs) -> Self {
+        Self {
+            threshold_db: args.compressor_threshold_db,
+            ratio: args.compressor_ratio,
+            knee_db: args.compressor_knee_db,
+            attack: Duration::from_secs_f64(args.compressor_attack_ms / 1e3),
+            release: Duration::from_secs_f64(args.compressor_release_ms / 1e3),
+            makeup_db: args.compressor_makeup_db,
+            lookahead: Duration::from_secs_f64(args.compressor_lookahead_ms / 1e3),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct RecoveryArgs {
+    /// Delay before rebuilding the route after a device is lost, doubled on every failed attempt
+    #[arg(long, value_name = "MS", default_value_t = 500)]
+    retry_backoff_ms: u64,
+
+    /// Upper bound for the retry delay
+    #[arg(long, value_name = "MS", default_value_t = 10_000)]
+    max_backoff_ms: u64,
+
+    /// Give up after this many consecutive failed attempts [default: retry forever]
+    #[arg(long, value_name = "N")]
+    max_retries: Option<u32>,
+
+    /// Use the default devices while the selected ones are unavailable
+    #[arg(long)]
+    fallback_to_default: bool,
+}
+
+impl From<&RecoveryArgs> for RecoveryPolicy {
+    fn from(args: &RecoveryArgs) -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(args.retry_backoff_ms),
+            max_backoff: Duration::from_millis(args.max_backoff_ms),
+            max_retries: args.max_retries,
+            fallback_to_default: args.fallback_to_default,
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Render a WAV file through the audio path without opening any device
+    Render(RenderArgs),
+    /// Measure the round-trip latency through a loopback from the output to the input device
+    MeasureLatency(MeasureArgs),
+}
+
+#[derive(Args, Debug)]
+struct MeasureArgs {
+    /// Longest round trip to search for
+    #[arg(long, value_name = "MS", default_value_t = 1000)]
+    max_round_trip_ms: u64,
+}
+
+#[derive(Args, Debug)]
+struct RenderArgs {
+    /// The WAV file to read
+    #[arg(long = "in", value_name = "FILE")]
+    input: PathBuf,
+
+    /// The WAV file to write
+    #[arg(long = "out", value_name = "FILE")]
+    output: PathBuf,
+
+    /// Output channel count [default: same as the input file]
+    #[arg(short, long)]
+    channels: Option<u16>,
+}
+
+/// Checks a device selector while the arguments are parsed, so a malformed one fails at startup
+/// instead of when the devices are opened.
+fn device_selector(s: &str) -> anyhow::Result<String> {
+    s.parse::<DeviceSelector>()?;
+    Ok(s.to_string())
 }
 
 fn init_logging() -> anyhow::Result<()> {
@@ -34,7 +396,51 @@ fn init_logging() -> anyhow::Result<()> {
     Ok(())
 }
 
-fn serve() -> anyhow::Result<()> {
+/// The route settings shared by the live and render modes.
+fn sidetone(args: &Cli) -> Sidetone {
+    let mut sidetone = Sidetone::new()
+        .input_device(args.input_device.as_str())
+        .output_device(args.output_device.as_str())
+        .latency(Duration::from_millis(args.latency_ms))
+        .resampler_quality(args.resample_quality)
+        .concealment(args.concealment)
+        .overflow(args.overflow)
+        .fade_in(Duration::from_millis(args.fade_in_ms))
+        .fade_out(Duration::from_millis(args.fade_out_ms))
+        .gain_db(args.gain_db)
+        .ceiling_db(args.ceiling_db)
+        .drift_compensation(!args.no_drift_compensation)
+        .input_config(StreamRequest {
+            sample_rate: args.input_rate,
+            channels: args.input_channels,
+            sample_format: args.input_format,
+            buffer_frames: args.input_buffer_frames,
+        })
+        .output_config(StreamRequest {
+            sample_rate: args.output_rate,
+            channels: args.output_channels,
+            sample_format: args.output_format,
+            buffer_frames: args.output_buffer_frames,
+        });
+    if let Some(frames) = args.buffer_frames {
+        sidetone = sidetone.buffer_frames(frames);
+    }
+    if let Some(matrix) = &args.route {
+        sidetone = sidetone.routing(matrix.clone());
+    }
+    if let Some(settings) = &args.eq {
+        sidetone = sidetone.processor(Equalizer::new(settings.clone()));
+    }
+    if args.gate.gate {
+        sidetone = sidetone.processor(NoiseGate::new(GateSettings::from(&args.gate)));
+    }
+    if args.compressor.compressor {
+        sidetone = sidetone.processor(Compressor::new(CompressorSettings::from(&args.compressor)));
+    }
+    sidetone
+}
+
+fn serve(args: Cli) -> anyhow::Result<()> {
     let (tx, rx) = std::sync::mpsc::channel();
     ctrlc::set_handler(move || {
         if tx.send(()).is_err() {
@@ -42,98 +448,86 @@ fn serve() -> anyhow::Result<()> {
         }
     })
     .context("could not set ctrl-c handler")?;
-    rx.recv()?;
+    let backend = CpalBackend::from_host_name(&args.host)?;
+    let policy = RecoveryPolicy::from(&args.recovery);
+    let mut supervisor = Supervisor::new(
+        backend,
+        || {
+            let sidetone = sidetone(&args);
+            if args.auto_latency {
+                sidetone.auto_latency(Duration::from_millis(args.max_latency_ms))
+            } else {
+                sidetone
+            }
+        },
+        policy,
+    );
+    supervisor.run(&rx)?;
+    if let Some(route) = supervisor.route() {
+        if args.auto_latency {
+            info!("Settled on a latency of {:?}", route.latency());
+        }
+        debug!("route stats {:?}", route.stats());
+        route.shutdown(!args.no_drain)?;
+    }
+    debug!("route restarts {}", supervisor.restarts());
     Ok(())
 }
 
-fn find_input_device(device_name: &str, host: &Host) -> Option<Device> {
-    if device_name == "default" {
-        host.default_input_device()
-    } else {
-        host.input_devices()
-            .ok()?
-            .find(|x| x.name().map(|y| y == device_name).unwrap_or(false))
-    }
+fn render(sidetone: Sidetone, args: &RenderArgs) -> anyhow::Result<()> {
+    let stats = render_file(sidetone, &args.input, &args.output, args.channels)?;
+    info!("Rendered '{}' {:?}", args.output.display(), stats);
+    Ok(())
+}
+
+fn measure(args: &Cli, measure_args: &MeasureArgs) -> anyhow::Result<()> {
+    let backend = CpalBackend::from_host_name(&args.host)?;
+    let report = measure_latency(
+        &backend,
+        sidetone(args),
+        Duration::from_millis(measure_args.max_round_trip_ms),
+    )?;
+    print!("{}", report);
+    Ok(())
 }
 
-fn find_output_device(device_name: &str, host: &Host) -> Option<Device> {
-    if device_name == "default" {
-        host.default_output_device()
+fn print_devices(json: bool) -> anyhow::Result<()> {
+    let hosts = list_devices();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&hosts)?);
     } else {
-        host.output_devices()
-            .ok()?
-            .find(|x| x.name().map(|y| y == device_name).unwrap_or(false))
+        for host in hosts {
+            print!("{}", host);
+        }
     }
+    Ok(())
+}
+
+/// Fills the options left out on the command line from the config file.
+fn apply_settings_file(args: &mut Cli) -> anyhow::Result<()> {
+    let Some(path) = &args.config else {
+        return Ok(());
+    };
+    let file = SettingsFile::load(path)?;
+    args.route = args.route.take().or(file.route);
+    args.eq = args.eq.take().or(file.eq);
+    Ok(())
 }
 
 fn main() -> anyhow::Result<()> {
     init_logging()?;
-    let args = Cli::parse();
-    let host = cpal::default_host();
-    let input_device =
-        find_input_device(&args.input_device, &host).context("failed to find input device")?;
-    let output_device =
-        find_output_device(&args.output_device, &host).context("failed to find output device")?;
-    let input_config: cpal::StreamConfig = input_device.default_input_config()?.into();
-    debug!("input device config {:#?}", &input_config);
-    let output_config: cpal::StreamConfig = output_device.default_output_config()?.into();
-    debug!("output device config {:#?}", &output_config);
-    if input_config.sample_rate.0 != output_config.sample_rate.0 {
-        anyhow::bail!("The sampling frequency of the input device must be the same as the sampling frequency of the output device");
+    let mut args = Cli::parse();
+    apply_settings_file(&mut args)?;
+    match &args.command {
+        Some(Command::Render(render_args)) => {
+            // A file has a single clock, so there is no drift to compensate.
+            return render(sidetone(&args).drift_compensation(false), render_args);
+        }
+        Some(Command::MeasureLatency(measure_args)) => return measure(&args, measure_args),
+        None => {}
     }
-    let latency_frames = (INITIAL_LATENCY.as_secs() as f32) * output_config.sample_rate.0 as f32;
-    let latency_samples = latency_frames as usize * output_config.channels as usize;
-    let (sender, receiver) = sync_channel(BUFFER_SIZE);
-
-    for _ in 0..latency_samples {
-        sender.try_send(0.0).ok();
+    if args.list_devices {
+        return print_devices(args.json);
     }
-
-    let input_data_fn = move |data: &[f32], _: &cpal::InputCallbackInfo| {
-        let mut output_fell_behind = false;
-        for &sample in data {
-            if sender.try_send(sample).is_err() {
-                output_fell_behind = true;
-            }
-        }
-        if output_fell_behind {
-            debug!("output stream fell behind: try increasing latency");
-        }
-    };
-
-    let output_data_fn = move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-        let mut input_fell_behind = false;
-        for frame in data.chunks_mut(output_config.channels as usize) {
-            if let Ok(_sample) = receiver.try_recv() {
-                for sample in frame.iter_mut() {
-                    *sample = _sample;
-                }
-            } else {
-                input_fell_behind = true;
-            }
-        }
-        if input_fell_behind {
-            debug!("input stream fell behind: try increasing latency");
-        }
-    };
-
-    let err_fn = |err: cpal::StreamError| {
-        error!("an error occurred on stream: {}", err);
-    };
-
-    info!(
-        "Redirecting audio stream from device '{}' to '{}'",
-        input_device.name()?,
-        output_device.name()?
-    );
-    let input_stream =
-        input_device.build_input_stream(&input_config, input_data_fn, err_fn, None)?;
-    std::thread::sleep(INITIAL_LATENCY);
-    let output_stream =
-        output_device.build_output_stream(&output_config, output_data_fn, err_fn, None)?;
-    input_stream.play()?;
-    output_stream.play()?;
-
-    serve()?;
-    Ok(())
+    serve(args)
 }
@@ -0,0 +1,323 @@
+//! Measures the round-trip latency of a device pair through a loopback from output to input.
+
+use crate::{
+    backend::{AudioStream, Backend},
+    config::{negotiate_input, negotiate_output},
+    device::{find_input_device, find_output_device},
+    resample::{Quality, Resampler},
+    ring::{ring, RingReader},
+    route::Sidetone,
+};
+use anyhow::Context;
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tracing::{error, info};
+
+/// Taps of the maximal-length shift register generating the test sequence: 2^14 - 1 frames.
+const MLS_TAPS: [u32; 4] = [14, 13, 12, 2];
+const MLS_ORDER: u32 = 14;
+/// Level of the test sequence, well below full scale to keep the loopback out of clipping.
+const AMPLITUDE: f32 = 0.25;
+/// Extra capture time for callbacks and stream start-up beyond the sequence and the longest
+/// round trip searched for.
+const CAPTURE_MARGIN: Duration = Duration::from_millis(200);
+/// Below this normalised correlation the peak is taken to be noise.
+const MIN_CORRELATION: f32 = 0.3;
+
+/// The delay measured between playing the test sequence and capturing it again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RoundTrip {
+    pub latency: Duration,
+    /// The delay in output frames.
+    pub frames: usize,
+    pub sample_rate: u32,
+    /// Normalised correlation of the captured signal with the test sequence, up to 1.0.
+    pub correlation: f32,
+}
+
+/// The round trip through the devices next to the delay the route itself adds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencyReport {
+    /// Output plus input latency of the drivers and the hardware, including the loopback.
+    pub round_trip: RoundTrip,
+    /// Buffering and processing latency of the route between the two devices.
+    pub internal: Duration,
+}
+
+impl LatencyReport {
+    /// The delay from the microphone to the ear when the route runs on these devices.
+    pub fn total(&self) -> Duration {
+        self.round_trip.latency + self.internal
+    }
+}
+
+impl fmt::Display for LatencyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.total().as_secs_f64();
+        let share = |x: Duration| {
+            if total > 0.0 {
+                x.as_secs_f64() / total * 100.0
+            } else {
+                0.0
+            }
+        };
+        writeln!(
+            f,
+            "Driver round trip: {:>8.2} ms ({} frames at {} Hz, correlation {:.2}), {:.0}%",
+            self.round_trip.latency.as_secs_f64() * 1e3,
+            self.round_trip.frames,
+            self.round_trip.sample_rate,
+            self.round_trip.correlation,
+            share(self.round_trip.latency)
+        )?;
+        writeln!(
+            f,
+            "Internal:          {:>8.2} ms, {:.0}%",
+            self.internal.as_secs_f64() * 1e3,
+            share(self.internal)
+        )?;
+        writeln!(f, "Mic to ear:        {:>8.2} ms", total * 1e3)
+    }
+}
+
+/// Plays a maximum length sequence on the output device while capturing the input device, and
+/// finds the sequence in the capture by cross-correlation.
+///
+/// Both streams are started together, so the time between their starts counts towards the
+/// round trip; it is small next to the driver latency.
+pub struct LatencyProbe {
+    input_stream: Box<dyn AudioStream>,
+    output_stream: Box<dyn AudioStream>,
+    reader: RingReader,
+    input_channels: usize,
+    input_rate: u32,
+    output_rate: u32,
+    sequence: Arc<Vec<f32>>,
+    max_lag: usize,
+    /// Mono capture at the input rate.
+    captured: Vec<f32>,
+    capture_frames: usize,
+    failed: Arc<AtomicBool>,
+}
+
+impl LatencyProbe {
+    /// Opens the devices `sidetone` is configured with, with its stream configs, and prepares to
+    /// search for round trips up to `max_round_trip`. The probe stays paused until
+    /// [`LatencyProbe::start`].
+    pub fn build<B: Backend>(
+        backend: &B,
+        sidetone: &Sidetone,
+        max_round_trip: Duration,
+    ) -> anyhow::Result<Self> {
+        let input_device = find_input_device(sidetone.input_selector(), backend)
+            .context("failed to find input device")?;
+        let output_device = find_output_device(sidetone.output_selector(), backend)
+            .context("failed to find output device")?;
+        let input = negotiate_input(backend, &input_device, &sidetone.input_request())?;
+        let output = negotiate_output(backend, &output_device, &sidetone.output_request())?;
+        let input_config = &input.config;
+        let output_config = &output.config;
+        let input_rate = input_config.sample_rate.0;
+        let output_rate = output_config.sample_rate.0;
+        let input_channels = input_config.channels as usize;
+        let output_channels = output_config.channels as usize;
+
+        let sequence = Arc::new(mls());
+        let max_lag = (max_round_trip.as_secs_f64() * output_rate as f64).ceil() as usize;
+        let capture = Duration::from_secs_f64(sequence.len() as f64 / output_rate as f64)
+            + max_round_trip
+            + CAPTURE_MARGIN;
+        let capture_frames = (capture.as_secs_f64() * input_rate as f64).ceil() as usize;
+        let (mut writer, reader) = ring(capture_frames * input_channels);
+
+        let input_data_fn = move |data: &[f32]| {
+            writer.push_slice(data);
+        };
+        let output_sequence = Arc::clone(&sequence);
+        let mut position = 0;
+        let output_data_fn = move |data: &mut [f32]| {
+            for frame in data.chunks_mut(output_channels) {
+                let sample = output_sequence.get(position).copied().unwrap_or(0.0);
+                frame.fill(sample);
+                position += 1;
+            }
+        };
+        let failed = Arc::new(AtomicBool::new(false));
+        let err_fn = |failed: Arc<AtomicBool>| {
+            move |err: cpal::StreamError| {
+                error!("an error occurred on stream: {}", err);
+                failed.store(true, Ordering::Relaxed);
+            }
+        };
+        let input_stream = backend.build_input_stream(
+            &input_device,
+            input_config,
+            input.sample_format,
+            Box::new(input_data_fn),
+            Box::new(err_fn(Arc::clone(&failed))),
+        )?;
+        let output_stream = backend.build_output_stream(
+            &output_device,
+            output_config,
+            output.sample_format,
+            Box::new(output_data_fn),
+            Box::new(err_fn(Arc::clone(&failed))),
+        )?;
+        info!(
+            "Measuring the round trip from '{}' to '{}'",
+            backend.device_name(&output_device)?,
+            backend.device_name(&input_device)?
+        );
+        Ok(Self {
+            input_stream,
+            output_stream,
+            reader,
+            input_channels,
+            input_rate,
+            output_rate,
+            sequence,
+            max_lag,
+            captured: Vec::with_capacity(capture_frames),
+            capture_frames,
+            failed,
+        })
+    }
+
+    pub fn start(&self) -> anyhow::Result<()> {
+        self.input_stream.play()?;
+        self.output_stream.play()?;
+        Ok(())
+    }
+
+    /// Collects what the input stream captured so far and returns whether the capture is
+    /// complete. Fails when either stream reported an error.
+    pub fn poll(&mut self) -> anyhow::Result<bool> {
+        if self.failed.load(Ordering::Relaxed) {
+            anyhow::bail!("a stream failed during the measurement");
+        }
+        let mut frame = vec![0.0; self.input_channels];
+        while self.captured.len() < self.capture_frames && self.reader.len() >= self.input_channels
+        {
+            self.reader.pop_slice(&mut frame);
+            self.captured.push(frame.iter().sum());
+        }
+        Ok(self.captured.len() >= self.capture_frames)
+    }
+
+    /// Closes the streams and finds the test sequence in the capture.
+    pub fn analyze(self) -> anyhow::Result<RoundTrip> {
+        let Self {
+            input_stream,
+            output_stream,
+            mut captured,
+            input_rate,
+            output_rate,
+            sequence,
+            max_lag,
+            ..
+        } = self;
+        drop((input_stream, output_stream));
+        if input_rate != output_rate {
+            let mut resampler = Resampler::new(input_rate, output_rate, 1, Quality::High);
+            let frames = (captured.len() as u64 * output_rate as u64 / input_rate as u64) as usize;
+            let mut resampled = vec![0.0; frames];
+            resampler.push(&captured);
+            let produced = resampler.pull(&mut resampled);
+            resampled.truncate(produced);
+            captured = resampled;
+        }
+        let (frames, correlation) = correlate(&sequence, &captured, max_lag)
+            .context("the capture is shorter than the test sequence")?;
+        if correlation < MIN_CORRELATION {
+            anyhow::bail!(
+                "the test sequence was not found in the input (correlation {:.2}); \
+                 connect the output to the input",
+                correlation
+            );
+        }
+        Ok(RoundTrip {
+            latency: Duration::from_secs_f64(frames as f64 / output_rate as f64),
+            frames,
+            sample_rate: output_rate,
+            correlation,
+        })
+    }
+}
+
+/// Measures the round trip through the devices `sidetone` is configured with, then builds the
+/// route without starting it to learn its internal latency.
+pub fn measure_latency<B: Backend>(
+    backend: &B,
+    sidetone: Sidetone,
+    max_round_trip: Duration,
+) -> anyhow::Result<LatencyReport> {
+    let mut probe = LatencyProbe::build(backend, &sidetone, max_round_trip)?;
+    let timeout = Instant::now()
+        + Duration::from_secs_f64(probe.capture_frames as f64 / probe.input_rate as f64)
+        + Duration::from_secs(2);
+    probe.start()?;
+    while !probe.poll()? {
+        if Instant::now() >= timeout {
+            anyhow::bail!(
+                "captured only {} of {} frames; is the input device running?",
+                probe.captured.len(),
+                probe.capture_frames
+            );
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    let round_trip = probe.analyze()?;
+    let internal = sidetone.build(backend)?.latency();
+    Ok(LatencyReport {
+        round_trip,
+        internal,
+    })
+}
+
+/// One period of the maximum length sequence, as +/-[`AMPLITUDE`].
+fn mls() -> Vec<f32> {
+    let mask = (1 << MLS_ORDER) - 1;
+    let mut state: u32 = 1;
+    (0..mask)
+        .map(|_| {
+            let bit = MLS_TAPS
+                .iter()
+                .fold(0, |acc, tap| acc ^ ((state >> (tap - 1)) & 1));
+            state = ((state << 1) | bit) & mask;
+            if bit == 1 {
+                AMPLITUDE
+            } else {
+                -AMPLITUDE
+            }
+        })
+        .collect()
+}
+
+/// Finds the lag of `signal` in `capture` within `0..=max_lag` and returns it with the
+/// normalised correlation at that lag. Polarity is ignored, since a loopback may invert it.
+fn correlate(signal: &[f32], capture: &[f32], max_lag: usize) -> Option<(usize, f32)> {
+    let max_lag = max_lag.min(capture.len().checked_sub(signal.len())?);
+    let signal_energy: f64 = signal.iter().map(|&x| x as f64 * x as f64).sum();
+    let mut best = (0, 0.0);
+    for lag in 0..=max_lag {
+        let window = &capture[lag..lag + signal.len()];
+        let (mut dot, mut energy) = (0.0, 0.0);
+        for (&x, &y) in signal.iter().zip(window) {
+            dot += x as f64 * y as f64;
+            energy += y as f64 * y as f64;
+        }
+        if energy > 0.0 {
+            let correlation = dot.abs() / (signal_energy * energy).sqrt();
+            if correlation > best.1 {
+                best = (lag, correlation);
+            }
+        }
+    }
+    Some((best.0, best.1 as f32))
+}
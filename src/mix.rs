@@ -0,0 +1,210 @@
+//! Converts interleaved frames between the channel layouts of the input and output devices.
+
+use anyhow::Context;
+use std::{fmt, str::FromStr};
+
+/// A connection from one input channel to one output channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Crosspoint {
+    /// Zero-based input channel.
+    pub input: usize,
+    /// Zero-based output channel.
+    pub output: usize,
+    /// Linear gain applied on the way.
+    pub gain: f32,
+}
+
+/// An explicit mapping of input channels to output channels, replacing the default conversion
+/// between the two layouts. Output channels without a crosspoint are silent; an output channel
+/// fed by several crosspoints gets their sum.
+///
+/// Parsed from a comma-separated list of `<in>:<out>[:<gain dB>]` with one-based channel
+/// numbers, e.g. `3:1,5:2` plays input 3 on the left and input 5 on the right, and
+/// `1:1,1:2,2:1:-6,2:2:-6` adds input 2 at half amplitude to both sides.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RoutingMatrix {
+    crosspoints: Vec<Crosspoint>,
+}
+
+impl RoutingMatrix {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Connects a zero-based input channel to a zero-based output channel with a linear gain.
+    pub fn connect(mut self, input: usize, output: usize, gain: f32) -> Self {
+        self.crosspoints.push(Crosspoint {
+            input,
+            output,
+            gain,
+        });
+        self
+    }
+
+    pub fn crosspoints(&self) -> &[Crosspoint] {
+        &self.crosspoints
+    }
+}
+
+impl FromStr for RoutingMatrix {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let channel = |x: &str| -> anyhow::Result<usize> {
+            match x.trim().parse::<usize>() {
+                Ok(0) | Err(_) => anyhow::bail!("invalid channel '{}', channels count from 1", x),
+                Ok(channel) => Ok(channel - 1),
+            }
+        };
+        let mut matrix = Self::new();
+        for crosspoint in s.split(',') {
+            let mut parts = crosspoint.split(':');
+            let (Some(input), Some(output)) = (parts.next(), parts.next()) else {
+                anyhow::bail!(
+                    "invalid crosspoint '{}', expected <in>:<out>[:<gain dB>]",
+                    crosspoint
+                );
+            };
+            let gain = match parts.next() {
+                Some(db) => {
+                    let db: f32 = db
+                        .trim()
+                        .parse()
+                        .with_context(|| format!("invalid gain '{}' in '{}'", db, crosspoint))?;
+                    10_f32.powf(db / 20.0)
+                }
+                None => 1.0,
+            };
+            if parts.next().is_some() {
+                anyhow::bail!(
+                    "invalid crosspoint '{}', expected <in>:<out>[:<gain dB>]",
+                    crosspoint
+                );
+            }
+            matrix = matrix.connect(channel(input)?, channel(output)?, gain);
+        }
+        Ok(matrix)
+    }
+}
+
+impl fmt::Display for RoutingMatrix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, x) in self.crosspoints.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}:{}", x.input + 1, x.output + 1)?;
+            if x.gain != 1.0 {
+                write!(f, ":{:.1}", 20.0 * x.gain.log10())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A gain matrix from every input channel to every output channel.
+pub(crate) struct Mixer {
+    inputs: usize,
+    outputs: usize,
+    /// `outputs` rows of `inputs` gains.
+    gains: Vec<f32>,
+}
+
+impl Mixer {
+    /// The default conversion between two layouts, matching channels by position:
+    ///
+    /// - equal counts pass every channel through unchanged;
+    /// - a mono input is copied to every output channel;
+    /// - 5.1 in the usual L R C LFE Ls Rs order folds into stereo with the standard matrix:
+    ///   centre and each surround at -3 dB into their sides, the LFE left out;
+    /// - otherwise, when downmixing, output channel `o` gets the input channels `i` with
+    ///   `i % outputs == o`, and channels left over after the last full group of `outputs`,
+    ///   such as the centre of a 3-channel input, go to every output channel at equal power.
+    ///   A mono output thus gets every input channel, and 4 channels fold into stereo as left
+    ///   and right pairs;
+    /// - when upmixing, output channel `o` gets input channel `o % inputs`, so stereo fills 4 as
+    ///   L R L R.
+    ///
+    /// Every downmixed output channel is normalised so a signal common to all inputs keeps its
+    /// level.
+    pub(crate) fn new(inputs: usize, outputs: usize) -> Self {
+        let mut gains = vec![0.0; inputs * outputs];
+        if inputs >= outputs {
+            if inputs == 6 && outputs == 2 {
+                let side = std::f32::consts::FRAC_1_SQRT_2;
+                gains = vec![
+                    1.0, 0.0, side, 0.0, side, 0.0, // left
+                    0.0, 1.0, side, 0.0, 0.0, side, // right
+                ];
+            } else {
+                let grouped = inputs - inputs % outputs;
+                for input in 0..grouped {
+                    gains[(input % outputs) * inputs + input] = 1.0;
+                }
+                let spread = 1.0 / (outputs as f32).sqrt();
+                for input in grouped..inputs {
+                    for output in 0..outputs {
+                        gains[output * inputs + input] = spread;
+                    }
+                }
+            }
+            for row in gains.chunks_exact_mut(inputs) {
+                let sum: f32 = row.iter().sum();
+                row.iter_mut().for_each(|x| *x /= sum);
+            }
+        } else {
+            for output in 0..outputs {
+                gains[output * inputs + output % inputs] = 1.0;
+            }
+        }
+        Self {
+            inputs,
+            outputs,
+            gains,
+        }
+    }
+
+    /// The gains of `matrix`, checked against the channel counts of the devices.
+    pub(crate) fn with_routing(
+        matrix: &RoutingMatrix,
+        inputs: usize,
+        outputs: usize,
+    ) -> anyhow::Result<Self> {
+        let mut gains = vec![0.0; inputs * outputs];
+        for x in matrix.crosspoints() {
+            if x.input >= inputs {
+                anyhow::bail!(
+                    "input channel {} does not exist, the input device has {} channels",
+                    x.input + 1,
+                    inputs
+                );
+            }
+            if x.output >= outputs {
+                anyhow::bail!(
+                    "output channel {} does not exist, the output device has {} channels",
+                    x.output + 1,
+                    outputs
+                );
+            }
+            gains[x.output * inputs + x.input] += x.gain;
+        }
+        Ok(Self {
+            inputs,
+            outputs,
+            gains,
+        })
+    }
+
+    /// Mixes interleaved input frames into as many interleaved output frames. `output` decides
+    /// how many frames are mixed.
+    pub(crate) fn process(&self, input: &[f32], output: &mut [f32]) {
+        for (frame, source) in output
+            .chunks_exact_mut(self.outputs)
+            .zip(input.chunks_exact(self.inputs))
+        {
+            for (sample, row) in frame.iter_mut().zip(self.gains.chunks_exact(self.inputs)) {
+                *sample = row.iter().zip(source).map(|(gain, x)| gain * x).sum();
+            }
+        }
+    }
+}
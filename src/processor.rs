@@ -0,0 +1,76 @@
+/// A stage of audio processing applied on the route between the input and output devices.
+///
+/// Blocks are interleaved `f32` samples in the output device's sample rate and channel layout.
+/// `process` runs on the real-time audio thread, so it must not block or allocate.
+pub trait Processor: Send {
+    /// Called before the first block and whenever the stream format changes.
+    fn prepare(&mut self, sample_rate: u32, channels: u16);
+
+    /// Processes an interleaved block in place.
+    fn process(&mut self, block: &mut [f32]);
+
+    /// Clears any internal state such as filter memory or envelopes.
+    fn reset(&mut self) {}
+
+    /// The delay this processor adds to the signal, in frames.
+    fn latency(&self) -> usize {
+        0
+    }
+}
+
+/// An ordered chain of processors that runs them one after another.
+#[derive(Default)]
+pub struct Chain {
+    processors: Vec<Box<dyn Processor>>,
+}
+
+impl Chain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a processor to the end of the chain.
+    pub fn push(&mut self, processor: impl Processor + 'static) {
+        self.processors.push(Box::new(processor));
+    }
+
+    pub fn len(&self) -> usize {
+        self.processors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.processors.is_empty()
+    }
+}
+
+impl std::fmt::Debug for Chain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Chain")
+            .field("processors", &self.processors.len())
+            .finish()
+    }
+}
+
+impl Processor for Chain {
+    fn prepare(&mut self, sample_rate: u32, channels: u16) {
+        for processor in &mut self.processors {
+            processor.prepare(sample_rate, channels);
+        }
+    }
+
+    fn process(&mut self, block: &mut [f32]) {
+        for processor in &mut self.processors {
+            processor.process(block);
+        }
+    }
+
+    fn reset(&mut self) {
+        for processor in &mut self.processors {
+            processor.reset();
+        }
+    }
+
+    fn latency(&self) -> usize {
+        self.processors.iter().map(|x| x.latency()).sum()
+    }
+}
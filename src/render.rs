@@ -0,0 +1,139 @@
+use crate::{
+    backend::mock::{MockBackend, MockDevice},
+    route::{RouteStats, Sidetone},
+};
+use anyhow::Context;
+use std::{path::Path, time::Duration};
+use tracing::info;
+
+/// Frames handed to the route per callback while rendering, unless a buffer size is requested.
+const RENDER_PERIOD: usize = 256;
+
+/// Decoded interleaved audio.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Audio {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub samples: Vec<f32>,
+}
+
+impl Audio {
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels as usize
+    }
+}
+
+/// Pushes `input` through the same route callbacks the live streams use, without opening any
+/// device, and returns `output_channels` of audio at the input's sample rate.
+///
+/// The output is `latency` longer than the input so the tail of the input is not cut off.
+pub fn render(
+    sidetone: Sidetone,
+    input: &Audio,
+    output_channels: u16,
+) -> anyhow::Result<(Audio, RouteStats)> {
+    let period = sidetone
+        .requested_buffer_frames()
+        .map(|x| x as usize)
+        .unwrap_or(RENDER_PERIOD);
+    let backend = MockBackend::new()
+        .with_input_device(MockDevice::new(
+            "render input",
+            input.channels,
+            input.sample_rate,
+        ))
+        .with_output_device(MockDevice::new(
+            "render output",
+            output_channels,
+            input.sample_rate,
+        ))
+        .with_period(period);
+    // A file starts at its first sample rather than mid-signal, so there is no click to fade.
+    let route = sidetone
+        .input_device("default")
+        .output_device("default")
+        .fade_in(Duration::ZERO)
+        .build(&backend)?;
+    let latency_frames = (route.latency().as_secs_f64() * input.sample_rate as f64) as usize;
+    let frames = input.frames() + latency_frames;
+
+    let mut samples = input.samples.clone();
+    samples.resize(frames * input.channels as usize, 0.0);
+    backend.push_input_blocks(&samples, period);
+
+    route.start()?;
+    backend.run(frames.div_ceil(period));
+    let mut output = backend.take_output();
+    output.truncate(frames * output_channels as usize);
+
+    Ok((
+        Audio {
+            sample_rate: input.sample_rate,
+            channels: output_channels,
+            samples: output,
+        },
+        route.stats(),
+    ))
+}
+
+/// Renders the WAV file at `input` to a 32-bit float WAV file at `output`.
+///
+/// `output_channels` defaults to the channel count of the input file.
+pub fn render_file(
+    sidetone: Sidetone,
+    input: &Path,
+    output: &Path,
+    output_channels: Option<u16>,
+) -> anyhow::Result<RouteStats> {
+    let audio = read_wav(input)?;
+    info!(
+        "Rendering '{}' ({} Hz, {} channels, {} frames) to '{}'",
+        input.display(),
+        audio.sample_rate,
+        audio.channels,
+        audio.frames(),
+        output.display()
+    );
+    let (rendered, stats) = render(sidetone, &audio, output_channels.unwrap_or(audio.channels))?;
+    write_wav(output, &rendered)?;
+    Ok(stats)
+}
+
+/// Reads a WAV file of any integer or float sample format into `f32` samples.
+pub fn read_wav(path: &Path) -> anyhow::Result<Audio> {
+    let reader = hound::WavReader::open(path)
+        .with_context(|| format!("could not open '{}'", path.display()))?;
+    let spec = reader.spec();
+    let samples = match spec.sample_format {
+        hound::SampleFormat::Float => reader.into_samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .map(|x| x.map(|x| x as f32 / scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    Ok(Audio {
+        sample_rate: spec.sample_rate,
+        channels: spec.channels,
+        samples,
+    })
+}
+
+/// Writes `audio` as a 32-bit float WAV file.
+pub fn write_wav(path: &Path, audio: &Audio) -> anyhow::Result<()> {
+    let spec = hound::WavSpec {
+        channels: audio.channels,
+        sample_rate: audio.sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)
+        .with_context(|| format!("could not create '{}'", path.display()))?;
+    for &sample in &audio.samples {
+        writer.write_sample(sample)?;
+    }
+    writer.finalize()?;
+    Ok(())
+}
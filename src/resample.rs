@@ -0,0 +1,217 @@
+use std::{f64::consts::PI, fmt, str::FromStr};
+
+/// Fractional positions between two input frames for which kernel coefficients are tabulated.
+const PHASES: usize = 256;
+
+/// Trade-off between resampling quality, CPU cost and added latency.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Quality {
+    /// Linear interpolation: one frame of lookahead, audible aliasing on bright material.
+    Fast,
+    /// 16-tap windowed sinc, 8 frames of lookahead.
+    #[default]
+    Balanced,
+    /// 64-tap windowed sinc, 32 frames of lookahead.
+    High,
+}
+
+impl Quality {
+    /// Input frames the kernel reaches on each side of the interpolated position.
+    fn half_taps(self) -> usize {
+        match self {
+            Self::Fast => 1,
+            Self::Balanced => 8,
+            Self::High => 32,
+        }
+    }
+}
+
+impl FromStr for Quality {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fast" => Ok(Self::Fast),
+            "balanced" => Ok(Self::Balanced),
+            "high" => Ok(Self::High),
+            _ => anyhow::bail!(
+                "unknown resampler quality '{}', expected fast, balanced or high",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fast => write!(f, "fast"),
+            Self::Balanced => write!(f, "balanced"),
+            Self::High => write!(f, "high"),
+        }
+    }
+}
+
+/// Streaming polyphase resampler for interleaved audio with an adjustable ratio.
+///
+/// Input is pushed in arbitrary amounts and output pulled in arbitrary amounts; the resampler
+/// buffers only what the kernel still needs.
+pub struct Resampler {
+    channels: usize,
+    half_taps: usize,
+    /// `PHASES + 1` rows of `2 * half_taps` coefficients, each row normalised to unity gain.
+    table: Vec<f32>,
+    nominal_step: f64,
+    /// Input frames advanced per output frame.
+    step: f64,
+    /// Interleaved input frames, starting with the oldest frame the kernel still needs.
+    buffer: Vec<f32>,
+    /// Position of the next output frame in `buffer`, in input frames.
+    position: f64,
+}
+
+impl Resampler {
+    pub fn new(input_rate: u32, output_rate: u32, channels: u16, quality: Quality) -> Self {
+        let half_taps = quality.half_taps();
+        let nominal_step = input_rate as f64 / output_rate as f64;
+        // When downsampling the cutoff moves below the output Nyquist frequency to avoid aliasing.
+        // At equal rates a full-band kernel passes samples through unchanged until the ratio is
+        // adjusted.
+        let cutoff = if input_rate == output_rate {
+            1.0
+        } else {
+            (1.0 / nominal_step).min(1.0) * 0.95
+        };
+        let taps = 2 * half_taps;
+        let mut table = Vec::with_capacity((PHASES + 1) * taps);
+        for phase in 0..=PHASES {
+            let frac = phase as f64 / PHASES as f64;
+            let row: Vec<f64> = (0..taps)
+                .map(|j| {
+                    let t = (j as f64 - half_taps as f64 + 1.0) - frac;
+                    if quality == Quality::Fast {
+                        (1.0 - t.abs()).max(0.0)
+                    } else {
+                        sinc(cutoff * t) * blackman(t / half_taps as f64)
+                    }
+                })
+                .collect();
+            let sum: f64 = row.iter().sum();
+            table.extend(row.iter().map(|x| (x / sum) as f32));
+        }
+        let mut resampler = Self {
+            channels: channels as usize,
+            half_taps,
+            table,
+            nominal_step,
+            step: nominal_step,
+            buffer: Vec::new(),
+            position: 0.0,
+        };
+        resampler.reset();
+        resampler
+    }
+
+    /// Clears buffered input. The next output frame is the next input frame pushed.
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.buffer.resize(self.half_taps * self.channels, 0.0);
+        self.position = self.half_taps as f64;
+    }
+
+    /// Scales the conversion ratio by `factor`, e.g. `1.0001` consumes input 0.01% faster.
+    pub fn set_ratio_adjustment(&mut self, factor: f64) {
+        self.step = self.nominal_step * factor;
+    }
+
+    /// Delay added by the kernel lookahead, in input frames.
+    pub fn latency(&self) -> usize {
+        self.half_taps
+    }
+
+    fn buffered_frames(&self) -> usize {
+        self.buffer.len() / self.channels
+    }
+
+    /// Input frames that still have to be pushed before `output_frames` can be pulled.
+    pub fn input_frames_needed(&self, output_frames: usize) -> usize {
+        if output_frames == 0 {
+            return 0;
+        }
+        let last = self.position + (output_frames - 1) as f64 * self.step;
+        (last.floor() as usize + self.half_taps + 1).saturating_sub(self.buffered_frames())
+    }
+
+    /// The most input frames [`Resampler::input_frames_needed`] asks for before `output_frames`
+    /// can be pulled, with the ratio adjusted by up to `max_adjustment` either way.
+    pub fn max_input_frames(&self, output_frames: usize, max_adjustment: f64) -> usize {
+        let step = self.nominal_step * (1.0 + max_adjustment);
+        (output_frames as f64 * step).ceil() as usize + 2 * self.half_taps + 1
+    }
+
+    /// Sets aside room for the input of pulls of up to `output_frames` frames, so that pushing
+    /// what [`Resampler::input_frames_needed`] asks for does not allocate.
+    pub fn reserve(&mut self, output_frames: usize, max_adjustment: f64) {
+        let frames = self.max_input_frames(output_frames, max_adjustment);
+        self.buffer
+            .reserve((frames * self.channels).saturating_sub(self.buffer.len()));
+    }
+
+    /// Appends interleaved input frames.
+    pub fn push(&mut self, input: &[f32]) {
+        self.buffer.extend_from_slice(input);
+    }
+
+    /// Fills `output` with as many interleaved frames as the buffered input allows and returns
+    /// the number of frames written.
+    pub fn pull(&mut self, output: &mut [f32]) -> usize {
+        let taps = 2 * self.half_taps;
+        let available = self.buffered_frames();
+        let mut produced = 0;
+        for frame in output.chunks_exact_mut(self.channels) {
+            let index = self.position.floor() as usize;
+            if index + self.half_taps >= available {
+                break;
+            }
+            let phase = (self.position - index as f64) * PHASES as f64;
+            let row = (phase.floor() as usize).min(PHASES - 1);
+            let weight = (phase - row as f64) as f32;
+            let low = &self.table[row * taps..(row + 1) * taps];
+            let high = &self.table[(row + 1) * taps..(row + 2) * taps];
+            let start = index + 1 - self.half_taps;
+            for (channel, out) in frame.iter_mut().enumerate() {
+                let mut sum = 0.0;
+                for tap in 0..taps {
+                    let coefficient = low[tap] + (high[tap] - low[tap]) * weight;
+                    sum += coefficient * self.buffer[(start + tap) * self.channels + channel];
+                }
+                *out = sum;
+            }
+            self.position += self.step;
+            produced += 1;
+        }
+        // Drop the frames the kernel will never reach again.
+        let keep_from = (self.position.floor() as usize + 1).saturating_sub(self.half_taps);
+        let drop = keep_from.min(self.buffered_frames());
+        self.buffer.drain(..drop * self.channels);
+        self.position -= drop as f64;
+        produced
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-9 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Blackman window over `x` in `-1.0..=1.0`.
+fn blackman(x: f64) -> f64 {
+    if x.abs() >= 1.0 {
+        return 0.0;
+    }
+    let x = (x + 1.0) / 2.0;
+    0.42 - 0.5 * (2.0 * PI * x).cos() + 0.08 * (4.0 * PI * x).cos()
+}
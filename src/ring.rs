@@ -0,0 +1,100 @@
+//! Wait-free single-producer single-consumer sample buffer between the input and output callbacks.
+
+/// Creates a preallocated ring buffer holding up to `capacity` samples.
+pub(crate) fn ring(capacity: usize) -> (RingWriter, RingReader) {
+    let (producer, consumer) = rtrb::RingBuffer::new(capacity);
+    (
+        RingWriter {
+            producer,
+            written: 0,
+        },
+        RingReader { consumer, read: 0 },
+    )
+}
+
+pub(crate) struct RingWriter {
+    producer: rtrb::Producer<f32>,
+    /// Samples written since the ring was created.
+    written: u64,
+}
+
+impl RingWriter {
+    /// Writes as many samples of `data` as fit and returns how many were written.
+    pub(crate) fn push_slice(&mut self, data: &[f32]) -> usize {
+        let n = data.len().min(self.producer.slots());
+        let n = match self.producer.write_chunk_uninit(n) {
+            Ok(chunk) => chunk.fill_from_iter(data.iter().copied()),
+            Err(_) => 0,
+        };
+        self.written += n as u64;
+        n
+    }
+
+    /// Number of samples that can be written without overflowing.
+    pub(crate) fn free(&self) -> usize {
+        self.producer.slots()
+    }
+
+    /// Writes `n` samples of silence, as far as they fit.
+    pub(crate) fn push_silence(&mut self, n: usize) -> usize {
+        let n = n.min(self.producer.slots());
+        let n = match self.producer.write_chunk_uninit(n) {
+            Ok(chunk) => chunk.fill_from_iter(std::iter::repeat(0.0)),
+            Err(_) => 0,
+        };
+        self.written += n as u64;
+        n
+    }
+
+    /// Position of the next sample written, counted in samples since the ring was created.
+    pub(crate) fn position(&self) -> u64 {
+        self.written
+    }
+}
+
+pub(crate) struct RingReader {
+    consumer: rtrb::Consumer<f32>,
+    /// Samples read or discarded since the ring was created.
+    read: u64,
+}
+
+impl RingReader {
+    /// Reads as many samples into `out` as are available and returns how many were read.
+    pub(crate) fn pop_slice(&mut self, out: &mut [f32]) -> usize {
+        let n = out.len().min(self.consumer.slots());
+        let Ok(chunk) = self.consumer.read_chunk(n) else {
+            return 0;
+        };
+        let (first, second) = chunk.as_slices();
+        out[..first.len()].copy_from_slice(first);
+        out[first.len()..n].copy_from_slice(second);
+        chunk.commit_all();
+        self.read += n as u64;
+        n
+    }
+
+    /// Discards up to `n` samples and returns how many were discarded.
+    pub(crate) fn skip(&mut self, n: usize) -> usize {
+        let n = n.min(self.consumer.slots());
+        let n = match self.consumer.read_chunk(n) {
+            Ok(chunk) => {
+                chunk.commit_all();
+                n
+            }
+            Err(_) => 0,
+        };
+        self.read += n as u64;
+        n
+    }
+
+    /// Discards the samples before `position`, a [`RingWriter::position`], and returns how many
+    /// were discarded.
+    pub(crate) fn skip_to(&mut self, position: u64) -> usize {
+        self.skip(position.saturating_sub(self.read) as usize)
+    }
+
+    /// Number of samples waiting to be read.
+    pub(crate) fn len(&self) -> usize {
+        self.consumer.slots()
+    }
+}
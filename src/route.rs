@@ -0,0 +1,846 @@
+use crate::{
+    backend::{AudioStream, Backend},
+    conceal::{Concealer, Concealment, OverflowPolicy},
+    config::{negotiate_input, negotiate_output, NegotiatedConfig, StreamRequest},
+    device::{find_input_device, find_output_device},
+    drift::{DriftController, MAX_ADJUSTMENT},
+    fade::Fader,
+    gain::{db_to_linear, gain_to_linear, Gain},
+    limiter::Limiter,
+    mix::{Mixer, RoutingMatrix},
+    processor::{Chain, Processor},
+    resample::{Quality, Resampler},
+    ring::{ring, RingReader},
+    tuner::{Adjustment, LatencyTuner},
+};
+use anyhow::Context;
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicI64, AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tracing::{error, info};
+
+pub const DEFAULT_LATENCY: Duration = Duration::from_millis(50);
+pub const DEFAULT_FADE: Duration = Duration::from_millis(10);
+/// Extra time [`Route::shutdown`] waits for the output to fall silent.
+const SHUTDOWN_MARGIN: Duration = Duration::from_millis(200);
+/// Callback size assumed when sizing the ring buffer and the output scratch buffers while the
+/// driver picks the buffer size. Larger output callbacks are processed in chunks of this size.
+const DEFAULT_PERIOD_FRAMES: usize = 2048;
+
+/// A setting of the route that the chosen devices cannot satisfy. Unlike a missing or busy
+/// device, it does not go away by retrying the build.
+#[derive(Debug)]
+pub(crate) struct InvalidSettings(pub(crate) String);
+
+impl fmt::Display for InvalidSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for InvalidSettings {}
+
+/// Builder for a [`Route`] that redirects audio from an input device to an output device.
+#[derive(Debug)]
+pub struct Sidetone {
+    input_device: String,
+    output_device: String,
+    latency: Duration,
+    buffer_frames: Option<u32>,
+    input_request: StreamRequest,
+    output_request: StreamRequest,
+    auto_latency: Option<Duration>,
+    resampler_quality: Quality,
+    drift_compensation: bool,
+    concealment: Concealment,
+    overflow: OverflowPolicy,
+    fade_in: Duration,
+    fade_out: Duration,
+    routing: Option<RoutingMatrix>,
+    gain_db: f32,
+    ceiling_db: Option<f32>,
+    chain: Chain,
+}
+
+impl Default for Sidetone {
+    fn default() -> Self {
+        Self {
+            input_device: String::from("default"),
+            output_device: String::from("default"),
+            latency: DEFAULT_LATENCY,
+            buffer_frames: None,
+            input_request: StreamRequest::default(),
+            output_request: StreamRequest::default(),
+            auto_latency: None,
+            resampler_quality: Quality::default(),
+            drift_compensation: false,
+            concealment: Concealment::default(),
+            overflow: OverflowPolicy::default(),
+            fade_in: DEFAULT_FADE,
+            fade_out: DEFAULT_FADE,
+            routing: None,
+            gain_db: 0.0,
+            ceiling_db: None,
+            chain: Chain::new(),
+        }
+    }
+}
+
+impl Sidetone {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The input device, as a [`crate::device::DeviceSelector`] string.
+    pub fn input_device(mut self, name: impl Into<String>) -> Self {
+        self.input_device = name.into();
+        self
+    }
+
+    /// The output device, as a [`crate::device::DeviceSelector`] string.
+    pub fn output_device(mut self, name: impl Into<String>) -> Self {
+        self.output_device = name.into();
+        self
+    }
+
+    /// Delay between capturing a sample and playing it back.
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Tunes the latency at runtime instead of using [`Sidetone::latency`]: starts at the lowest
+    /// level the callback sizes allow, raises it after every underrun or overrun, and lowers it
+    /// again in small steps after long glitch-free stretches, never going beyond `max`.
+    pub fn auto_latency(mut self, max: Duration) -> Self {
+        self.auto_latency = Some(max);
+        self
+    }
+
+    /// Fixed callback buffer size in frames for both streams, instead of the driver's choice.
+    /// A buffer size in [`Sidetone::input_config`] or [`Sidetone::output_config`] takes
+    /// precedence for its side.
+    pub fn buffer_frames(mut self, frames: u32) -> Self {
+        self.buffer_frames = Some(frames);
+        self
+    }
+
+    /// The stream config to ask the input device for. The nearest supported config is used when
+    /// the device does not support it exactly.
+    pub fn input_config(mut self, request: StreamRequest) -> Self {
+        self.input_request = request;
+        self
+    }
+
+    /// The stream config to ask the output device for, negotiated like
+    /// [`Sidetone::input_config`].
+    pub fn output_config(mut self, request: StreamRequest) -> Self {
+        self.output_request = request;
+        self
+    }
+
+    /// The fixed buffer size requested with [`Sidetone::buffer_frames`], if any.
+    pub fn requested_buffer_frames(&self) -> Option<u32> {
+        self.buffer_frames
+    }
+
+    /// Quality of the sample rate conversion used when the two devices run at different rates.
+    pub fn resampler_quality(mut self, quality: Quality) -> Self {
+        self.resampler_quality = quality;
+        self
+    }
+
+    /// Continuously adjusts the resampling ratio to hold the buffer at the latency target when the
+    /// input and output clocks drift apart. Resamples even between devices at the same rate.
+    pub fn drift_compensation(mut self, enabled: bool) -> Self {
+        self.drift_compensation = enabled;
+        self
+    }
+
+    /// What the output plays while the input has not delivered enough audio.
+    pub fn concealment(mut self, concealment: Concealment) -> Self {
+        self.concealment = concealment;
+        self
+    }
+
+    /// Which audio is dropped when the input delivers more than the buffer can hold.
+    pub fn overflow(mut self, overflow: OverflowPolicy) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Length of the ramp up from silence when the route starts and when it is unmuted.
+    pub fn fade_in(mut self, fade: Duration) -> Self {
+        self.fade_in = fade;
+        self
+    }
+
+    /// Length of the ramp down to silence when the route shuts down and when it is muted.
+    pub fn fade_out(mut self, fade: Duration) -> Self {
+        self.fade_out = fade;
+        self
+    }
+
+    /// Maps input channels to output channels explicitly instead of converting between the two
+    /// layouts by channel position.
+    pub fn routing(mut self, matrix: RoutingMatrix) -> Self {
+        self.routing = Some(matrix);
+        self
+    }
+
+    /// Level of the monitored signal in dB, at most [`crate::gain::MAX_GAIN_DB`]. Can be changed
+    /// later with [`Route::set_gain_db`].
+    pub fn gain_db(mut self, db: f32) -> Self {
+        self.gain_db = db;
+        self
+    }
+
+    /// Highest output level in dBFS, at most 0, enforced by a true-peak brickwall limiter after
+    /// everything else on the route, so no gain or processor setting can play louder. The
+    /// limiter adds about a millisecond of latency. Without a ceiling the output is not limited.
+    pub fn ceiling_db(mut self, db: f32) -> Self {
+        self.ceiling_db = Some(db);
+        self
+    }
+
+    /// Appends a processor to the chain run on the monitored signal.
+    pub fn processor(mut self, processor: impl Processor + 'static) -> Self {
+        self.chain.push(processor);
+        self
+    }
+
+    /// The input stream request, with the buffer size of [`Sidetone::buffer_frames`] unless it
+    /// asks for its own.
+    pub(crate) fn input_request(&self) -> StreamRequest {
+        StreamRequest {
+            buffer_frames: self.input_request.buffer_frames.or(self.buffer_frames),
+            ..self.input_request
+        }
+    }
+
+    /// The output stream request, completed like [`Sidetone::input_request`].
+    pub(crate) fn output_request(&self) -> StreamRequest {
+        StreamRequest {
+            buffer_frames: self.output_request.buffer_frames.or(self.buffer_frames),
+            ..self.output_request
+        }
+    }
+
+    /// The input device selector this route was configured with.
+    pub fn input_selector(&self) -> &str {
+        &self.input_device
+    }
+
+    /// The output device selector this route was configured with.
+    pub fn output_selector(&self) -> &str {
+        &self.output_device
+    }
+
+    /// Opens both devices and builds the streams. The route stays paused until [`Route::start`].
+    pub fn build<B: Backend>(mut self, backend: &B) -> anyhow::Result<Route> {
+        if let Some(db) = self.ceiling_db.filter(|x| x.is_nan() || *x > 0.0) {
+            return Err(InvalidSettings(format!(
+                "output ceiling of {} dBFS is above full scale",
+                db
+            ))
+            .into());
+        }
+        let input_device = find_input_device(&self.input_device, backend)
+            .context("failed to find input device")?;
+        let output_device = find_output_device(&self.output_device, backend)
+            .context("failed to find output device")?;
+        let negotiated_input = negotiate_input(backend, &input_device, &self.input_request())?;
+        let negotiated_output = negotiate_output(backend, &output_device, &self.output_request())?;
+        let input_config = &negotiated_input.config;
+        let output_config = &negotiated_output.config;
+        let input_rate = input_config.sample_rate.0;
+        let output_rate = output_config.sample_rate.0;
+        // The ring has to cover the longer of the two callbacks, counted in input frames.
+        let input_period = negotiated_input.buffer_frames().map(|x| x as usize);
+        let output_period = negotiated_output
+            .buffer_frames()
+            .map(|x| (x as u64 * input_rate as u64).div_ceil(output_rate as u64) as usize);
+        let fixed_period = input_period.max(output_period);
+        let period_frames = fixed_period.unwrap_or(DEFAULT_PERIOD_FRAMES);
+        let latency_frames = (self.latency.as_secs_f64() * input_rate as f64).round() as usize;
+        if self.auto_latency.is_none() && fixed_period.is_some() && latency_frames < period_frames {
+            return Err(InvalidSettings(format!(
+                "latency of {:?} is shorter than one buffer of {} frames at {} Hz",
+                self.latency, period_frames, input_rate
+            ))
+            .into());
+        }
+        // The ring carries interleaved input frames.
+        let samples_per_frame = input_config.channels as usize;
+        let samples_per_second = input_rate as f64 * samples_per_frame as f64;
+        let max_latency_frames = self
+            .auto_latency
+            .map(|max| (max.as_secs_f64() * input_rate as f64).round() as usize);
+        let tuner = max_latency_frames.map(|max| {
+            // Without a fixed buffer size the floor is learned from the first callbacks.
+            let floor = fixed_period.unwrap_or(0) * samples_per_frame;
+            let ceiling = max * samples_per_frame;
+            let step = ((0.001 * input_rate as f64).round() as usize).max(1) * samples_per_frame;
+            info!(
+                "Tuning the latency automatically, up to {:?}",
+                self.auto_latency.unwrap_or_default()
+            );
+            LatencyTuner::new(floor, ceiling, step)
+        });
+        let latency_samples = match &tuner {
+            Some(tuner) => tuner.target(),
+            None => latency_frames * samples_per_frame,
+        };
+        let max_latency_samples = match max_latency_frames {
+            Some(max) => max * samples_per_frame,
+            None => latency_samples,
+        };
+        let period_samples = period_frames * samples_per_frame;
+        // The ring holds the primed latency plus room for the input to run a callback or two ahead.
+        let (mut writer, reader) = ring(2 * (max_latency_samples + period_samples));
+        writer.push_silence(latency_samples);
+
+        let counters = Arc::new(Counters::default());
+        counters
+            .latency_target
+            .store(latency_samples as u64, Ordering::Relaxed);
+        counters.silent.store(true, Ordering::Relaxed);
+        let gain = gain_to_linear(self.gain_db);
+        counters.gain.store(gain.to_bits(), Ordering::Relaxed);
+
+        let input_counters = Arc::clone(&counters);
+        let overflow = self.overflow;
+        let input_data_fn = move |data: &[f32]| {
+            input_counters
+                .input_block
+                .fetch_max(data.len() as u64, Ordering::Relaxed);
+            if input_counters.draining.load(Ordering::Relaxed) {
+                return;
+            }
+            let free = writer.free();
+            if free >= data.len() {
+                writer.push_slice(data);
+                return;
+            }
+            input_counters.overruns.fetch_add(1, Ordering::Relaxed);
+            // Only whole frames are written, so the ring never splits a frame.
+            let keep = free - free % samples_per_frame;
+            match overflow {
+                OverflowPolicy::DropNewest => {
+                    writer.push_slice(&data[..keep]);
+                }
+                OverflowPolicy::DropOldest => {
+                    // Everything queued so far is older than the part of the block that fits, so
+                    // the output discards it instead.
+                    input_counters
+                        .flush
+                        .store(writer.position(), Ordering::Relaxed);
+                    writer.push_slice(&data[data.len() - keep..]);
+                }
+            }
+        };
+
+        let mut resampler = (input_rate != output_rate || self.drift_compensation).then(|| {
+            info!(
+                "Resampling from {} Hz to {} Hz with {} quality",
+                input_rate, output_rate, self.resampler_quality
+            );
+            Resampler::new(
+                input_rate,
+                output_rate,
+                input_config.channels,
+                self.resampler_quality,
+            )
+        });
+        let drift = self
+            .drift_compensation
+            .then(|| DriftController::new(samples_per_second, output_rate));
+        self.chain.prepare(output_rate, output_config.channels);
+        let input_channels = input_config.channels as usize;
+        let output_channels = output_config.channels as usize;
+        let mixer = match &self.routing {
+            Some(matrix) => {
+                info!("Routing channels {}", matrix);
+                Mixer::with_routing(matrix, input_channels, output_channels)
+                    .context(InvalidSettings(String::from("invalid channel routing")))?
+            }
+            None => {
+                if input_channels != output_channels {
+                    info!(
+                        "Mixing {} input channels into {} output channels",
+                        input_channels, output_channels
+                    );
+                }
+                Mixer::new(input_channels, output_channels)
+            }
+        };
+        let concealer = Concealer::new(
+            self.concealment,
+            output_rate,
+            input_config.channels,
+            (period_frames as u64 * output_rate as u64 / input_rate as u64) as usize,
+        );
+        let fade_frames =
+            |fade: Duration| (fade.as_secs_f64() * output_rate as f64).round() as usize;
+        let fall_frames = fade_frames(self.fade_out);
+        let mut fader = Fader::new(fade_frames(self.fade_in), fall_frames);
+        // The fade in starts with the first input, after the primed silence.
+        fader.hold(
+            (latency_samples as f64 / samples_per_second * output_rate as f64).round() as usize,
+        );
+        let resampler_latency = resampler.as_ref().map(|x| x.latency()).unwrap_or(0);
+        // With automatic tuning the buffering part of the latency is read from the counters.
+        let buffer_latency = match tuner {
+            Some(_) => Duration::ZERO,
+            None => self.latency,
+        };
+        let limiter = self.ceiling_db.map(|db| {
+            Limiter::new(
+                db_to_linear(db),
+                output_rate,
+                output_config.channels as usize,
+            )
+        });
+        let output_latency = self.chain.latency() + limiter.as_ref().map_or(0, |x| x.latency());
+        let latency = buffer_latency
+            + Duration::from_secs_f64(resampler_latency as f64 / input_rate as f64)
+            + Duration::from_secs_f64(output_latency as f64 / output_rate as f64);
+
+        // Every buffer of the output callback is sized here; longer callbacks are split up.
+        let max_frames = negotiated_output
+            .buffer_frames()
+            .map_or(DEFAULT_PERIOD_FRAMES, |x| x as usize);
+        let scratch = resampler.as_mut().map_or(0, |x| {
+            x.reserve(max_frames, MAX_ADJUSTMENT);
+            x.max_input_frames(max_frames, MAX_ADJUSTMENT) * samples_per_frame
+        });
+        let mut output = OutputStage {
+            reader,
+            channels: output_config.channels as usize,
+            resampler,
+            drift,
+            chain: self.chain,
+            mixer,
+            concealer,
+            fader,
+            fall_frames,
+            gain: Gain::new(gain, output_rate),
+            limiter,
+            tuner,
+            samples_per_frame,
+            samples_per_second,
+            output_rate: output_rate as f64,
+            refilling: false,
+            overruns: 0,
+            max_frames,
+            scratch: vec![0.0; scratch],
+            block: vec![0.0; max_frames * samples_per_frame],
+            counters: Arc::clone(&counters),
+        };
+        let output_data_fn = move |data: &mut [f32]| output.process(data);
+
+        let err_fn = |counters: Arc<Counters>| {
+            move |err: cpal::StreamError| {
+                error!("an error occurred on stream: {}", err);
+                match err {
+                    cpal::StreamError::DeviceNotAvailable => {
+                        counters.device_lost.store(true, Ordering::Relaxed)
+                    }
+                    cpal::StreamError::BackendSpecific { .. } => {
+                        counters.stream_errors.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+        };
+
+        let input_name = backend.device_name(&input_device)?;
+        let output_name = backend.device_name(&output_device)?;
+        let input_stream = backend.build_input_stream(
+            &input_device,
+            input_config,
+            negotiated_input.sample_format,
+            Box::new(input_data_fn),
+            Box::new(err_fn(Arc::clone(&counters))),
+        )?;
+        let output_stream = backend.build_output_stream(
+            &output_device,
+            output_config,
+            negotiated_output.sample_format,
+            Box::new(output_data_fn),
+            Box::new(err_fn(Arc::clone(&counters))),
+        )?;
+
+        Ok(Route {
+            input_name,
+            output_name,
+            input_stream,
+            output_stream,
+            latency,
+            fade_out: self.fade_out,
+            tuned_latency: self.auto_latency.map(|_| samples_per_second),
+            counters,
+            input_config: negotiated_input,
+            output_config: negotiated_output,
+        })
+    }
+}
+
+/// The half of the route that runs in the output stream callback.
+struct OutputStage {
+    reader: RingReader,
+    channels: usize,
+    resampler: Option<Resampler>,
+    drift: Option<DriftController>,
+    chain: Chain,
+    mixer: Mixer,
+    concealer: Concealer,
+    fader: Fader,
+    /// Length of the fade out, in output frames.
+    fall_frames: usize,
+    gain: Gain,
+    limiter: Option<Limiter>,
+    tuner: Option<LatencyTuner>,
+    /// Ring samples per frame: the input channel count.
+    samples_per_frame: usize,
+    /// Ring samples per second of buffering latency.
+    samples_per_second: f64,
+    output_rate: f64,
+    /// Whether playback waits for the buffer to fill up to the tuner target.
+    refilling: bool,
+    /// Overruns already seen by the tuner.
+    overruns: u64,
+    /// Output frames rendered at once, which the scratch buffers are sized for.
+    max_frames: usize,
+    /// Samples popped from the ring for the resampler.
+    scratch: Vec<f32>,
+    /// Input frames at the output rate, before they are mixed into the output channels.
+    block: Vec<f32>,
+    counters: Arc<Counters>,
+}
+
+impl OutputStage {
+    fn process(&mut self, data: &mut [f32]) {
+        let frames = data.len() / self.channels;
+        if let Some(tuner) = &mut self.tuner {
+            let input_block = self.counters.input_block.load(Ordering::Relaxed) as usize;
+            let adjustment = tuner.raise_floor((frames * self.samples_per_frame).max(input_block));
+            self.adjust(adjustment);
+        }
+        let flush = self.counters.flush.load(Ordering::Relaxed);
+        if self.reader.skip_to(flush) > 0 {
+            // The input dropped the oldest audio: skip the rest of the backlog down to the latency
+            // target.
+            let target = self.counters.latency_target.load(Ordering::Relaxed) as usize;
+            let level = self.reader.len();
+            if level > target {
+                self.skip(level - target);
+            }
+            // Whatever remained of the primed silence is gone.
+            self.fader.hold(0);
+        }
+        let stopping = self.counters.stopping.load(Ordering::Relaxed);
+        let draining = self.counters.draining.load(Ordering::Relaxed);
+        // While draining, the fade out starts early enough to end with the buffered audio.
+        let drained = draining
+            && self.reader.len() as f64 / self.samples_per_second * self.output_rate
+                <= (self.fall_frames + frames) as f64;
+        self.fader.set_open(
+            !self.counters.muted.load(Ordering::Relaxed) && !(stopping && (!draining || drained)),
+        );
+        if self.refilling {
+            if let Some(tuner) = &self.tuner {
+                let level = self.reader.len();
+                if level < tuner.target() {
+                    for chunk in data.chunks_mut(self.max_frames * self.channels) {
+                        let samples = chunk.len() / self.channels * self.samples_per_frame;
+                        self.concealer.process(&mut self.block[..samples], 0);
+                        self.play(chunk);
+                    }
+                    return;
+                }
+                // The input may have overshot the target while playback waited.
+                let surplus = level - tuner.target();
+                self.skip(surplus);
+            }
+            self.refilling = false;
+        }
+        if let (Some(drift), Some(resampler)) = (&mut self.drift, &mut self.resampler) {
+            let adjustment = drift.update(self.reader.len(), frames);
+            resampler.set_ratio_adjustment(adjustment);
+            self.counters
+                .buffer_level
+                .store(drift.level() as u64, Ordering::Relaxed);
+            self.counters
+                .drift_ppm
+                .store(((adjustment - 1.0) * 1e6).round() as i64, Ordering::Relaxed);
+        }
+        let mut produced = 0;
+        for chunk in data.chunks_mut(self.max_frames * self.channels) {
+            produced += self.render(chunk);
+        }
+        if produced < frames {
+            self.counters.underruns.fetch_add(1, Ordering::Relaxed);
+        }
+        if let Some(tuner) = &mut self.tuner {
+            let overruns = self.counters.overruns.load(Ordering::Relaxed);
+            let glitch = produced < frames || overruns != self.overruns;
+            self.overruns = overruns;
+            let adjustment = tuner.update(glitch, frames as f64 / self.output_rate);
+            self.adjust(adjustment);
+            if produced < frames {
+                self.refilling = true;
+            }
+        }
+    }
+
+    /// Fills `data`, at most `max_frames` frames, from the ring, concealing what the ring could
+    /// not provide, and returns the number of frames that came from the ring.
+    fn render(&mut self, data: &mut [f32]) -> usize {
+        let frames = data.len() / self.channels;
+        let samples = frames * self.samples_per_frame;
+        let produced = match &mut self.resampler {
+            Some(resampler) => {
+                let needed = resampler.input_frames_needed(frames) * self.samples_per_frame;
+                let read = self.reader.pop_slice(&mut self.scratch[..needed]);
+                resampler.push(&self.scratch[..read]);
+                resampler.pull(&mut self.block[..samples])
+            }
+            None => self.reader.pop_slice(&mut self.block[..samples]) / self.samples_per_frame,
+        };
+        self.concealer.process(&mut self.block[..samples], produced);
+        self.play(data);
+        produced
+    }
+
+    /// Mixes the block into the output channels of `data`, runs the chain on it and applies the
+    /// fader.
+    fn play(&mut self, data: &mut [f32]) {
+        self.mixer.process(&self.block, data);
+        self.chain.process(data);
+        let gain = f32::from_bits(self.counters.gain.load(Ordering::Relaxed));
+        self.gain.apply(data, self.channels, gain);
+        self.fader.apply(data, self.channels);
+        if let Some(limiter) = &mut self.limiter {
+            let overs = limiter.process(data);
+            if overs > 0 {
+                self.counters
+                    .clip_events
+                    .fetch_add(overs, Ordering::Relaxed);
+            }
+        }
+        let drained = self.limiter.as_ref().is_none_or(|x| x.is_silent());
+        self.counters
+            .silent
+            .store(self.fader.is_closed() && drained, Ordering::Relaxed);
+    }
+
+    /// Discards up to `samples` of the oldest buffered audio, in whole frames.
+    fn skip(&mut self, samples: usize) {
+        self.reader.skip(samples - samples % self.samples_per_frame);
+    }
+
+    /// Moves the buffer level towards a new tuner target. A higher target is reached by pausing
+    /// playback until the buffer has filled up; a lower one by letting the drift controller drain
+    /// the buffer smoothly, or else by dropping the surplus at once.
+    fn adjust(&mut self, adjustment: Adjustment) {
+        let Some(tuner) = &self.tuner else {
+            return;
+        };
+        let target = tuner.target();
+        match adjustment {
+            Adjustment::Unchanged => return,
+            Adjustment::Raised(delta) => {
+                if let Some(drift) = &mut self.drift {
+                    drift.shift_target(delta as f64);
+                }
+                self.refilling = true;
+            }
+            Adjustment::Lowered(delta) => match (&mut self.drift, &self.resampler) {
+                (Some(drift), Some(_)) => drift.shift_target(-(delta as f64)),
+                _ => self.skip(delta),
+            },
+        }
+        // Published for the supervisor to log; nothing is logged from the audio thread.
+        self.counters
+            .latency_target
+            .store(target as u64, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    overruns: AtomicU64,
+    underruns: AtomicU64,
+    stream_errors: AtomicU64,
+    device_lost: AtomicBool,
+    buffer_level: AtomicU64,
+    drift_ppm: AtomicI64,
+    /// Largest input callback seen, in samples.
+    input_block: AtomicU64,
+    /// Buffer level the route holds, in samples.
+    latency_target: AtomicU64,
+    /// Ring position before which the output discards the queued audio, moved forward by the
+    /// input when it drops the oldest audio.
+    flush: AtomicU64,
+    muted: AtomicBool,
+    /// Set by [`Route::fade_out`]; the output fades out and stays silent.
+    stopping: AtomicBool,
+    /// Set by [`Route::fade_out`] with draining; the input is ignored.
+    draining: AtomicBool,
+    /// Whether the output fader is fully closed.
+    silent: AtomicBool,
+    /// Linear gain the output moves towards, as `f32` bits.
+    gain: AtomicU32,
+    clip_events: AtomicU64,
+}
+
+/// A snapshot of the event counters of a running [`Route`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RouteStats {
+    /// Input callbacks that found the buffer full because the output stream fell behind.
+    pub overruns: u64,
+    /// Output callbacks that found the buffer empty because the input stream fell behind.
+    pub underruns: u64,
+    /// Backend-specific errors reported by either stream.
+    pub stream_errors: u64,
+    /// Whether either device reported that it is no longer available.
+    pub device_lost: bool,
+    /// Averaged number of buffered samples, tracked while drift compensation is on.
+    pub buffer_level: u64,
+    /// Current resampling ratio adjustment in parts per million, while drift compensation is on.
+    pub drift_ppm: i64,
+    /// Times the output would have gone over the ceiling and the limiter pulled it down.
+    pub clip_events: u64,
+}
+
+/// Handle to a built route. Dropping it closes both streams.
+pub struct Route {
+    input_name: String,
+    output_name: String,
+    input_stream: Box<dyn AudioStream>,
+    output_stream: Box<dyn AudioStream>,
+    latency: Duration,
+    fade_out: Duration,
+    /// Ring samples per second, when the buffering latency is tuned at runtime.
+    tuned_latency: Option<f64>,
+    counters: Arc<Counters>,
+    input_config: NegotiatedConfig,
+    output_config: NegotiatedConfig,
+}
+
+impl Route {
+    pub fn start(&self) -> anyhow::Result<()> {
+        info!(
+            "Redirecting audio stream from device '{}' to '{}'",
+            self.input_name, self.output_name
+        );
+        self.input_stream.play()?;
+        self.output_stream.play()?;
+        Ok(())
+    }
+
+    /// Pauses both streams at once, wherever the output is in its buffer.
+    pub fn stop(&self) -> anyhow::Result<()> {
+        self.input_stream.pause()?;
+        self.output_stream.pause()?;
+        Ok(())
+    }
+
+    /// Starts fading the output out without blocking. With `drain` the input is ignored from
+    /// now on and the fade ends with the audio still buffered; otherwise it starts at once.
+    pub fn fade_out(&self, drain: bool) {
+        self.counters.draining.store(drain, Ordering::Relaxed);
+        self.counters.stopping.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the output is fully faded out, by [`Route::fade_out`] or [`Route::set_muted`], or
+    /// has not played any input yet.
+    pub fn is_silent(&self) -> bool {
+        self.counters.silent.load(Ordering::Relaxed)
+    }
+
+    /// Fades the output out, optionally after draining the buffer, and stops the streams once it
+    /// is silent, or after the longest time that should take.
+    pub fn shutdown(&self, drain: bool) -> anyhow::Result<()> {
+        self.fade_out(drain);
+        let mut timeout = self.fade_out + SHUTDOWN_MARGIN;
+        if drain {
+            timeout += self.latency();
+        }
+        let deadline = Instant::now() + timeout;
+        while !self.is_silent() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        self.stop()
+    }
+
+    /// Ramps the output down to silence or back up to full level.
+    pub fn set_muted(&self, muted: bool) {
+        self.counters.muted.store(muted, Ordering::Relaxed);
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.counters.muted.load(Ordering::Relaxed)
+    }
+
+    /// Changes the level of the monitored signal, in dB up to [`crate::gain::MAX_GAIN_DB`]. The
+    /// output glides to the new level over a few tens of milliseconds.
+    pub fn set_gain_db(&self, db: f32) {
+        self.counters
+            .gain
+            .store(gain_to_linear(db).to_bits(), Ordering::Relaxed);
+    }
+
+    /// The level the output is set to, in dB.
+    pub fn gain_db(&self) -> f32 {
+        20.0 * f32::from_bits(self.counters.gain.load(Ordering::Relaxed)).log10()
+    }
+
+    pub fn stats(&self) -> RouteStats {
+        RouteStats {
+            overruns: self.counters.overruns.load(Ordering::Relaxed),
+            underruns: self.counters.underruns.load(Ordering::Relaxed),
+            stream_errors: self.counters.stream_errors.load(Ordering::Relaxed),
+            device_lost: self.counters.device_lost.load(Ordering::Relaxed),
+            buffer_level: self.counters.buffer_level.load(Ordering::Relaxed),
+            drift_ppm: self.counters.drift_ppm.load(Ordering::Relaxed),
+            clip_events: self.counters.clip_events.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The total delay of the route: the buffering latency plus the latency of the processing chain
+    /// and of the output limiter.
+    /// With [`Sidetone::auto_latency`] this is the current target of the tuner.
+    pub fn latency(&self) -> Duration {
+        match self.tuned_latency {
+            Some(samples_per_second) => {
+                let target = self.counters.latency_target.load(Ordering::Relaxed);
+                self.latency + Duration::from_secs_f64(target as f64 / samples_per_second)
+            }
+            None => self.latency,
+        }
+    }
+
+    pub fn input_name(&self) -> &str {
+        &self.input_name
+    }
+
+    pub fn output_name(&self) -> &str {
+        &self.output_name
+    }
+
+    /// The config the input stream was opened with.
+    pub fn input_config(&self) -> &NegotiatedConfig {
+        &self.input_config
+    }
+
+    /// The config the output stream was opened with.
+    pub fn output_config(&self) -> &NegotiatedConfig {
+        &self.output_config
+    }
+}
@@ -0,0 +1,52 @@
+//! Route settings read from a JSON file, written in the same syntax as the command line options.
+//!
+//! ```json
+//! { "route": "3:1,5:2", "eq": "highpass:100,peak:250:-4:1.5" }
+//! ```
+
+use crate::{eq::EqSettings, mix::RoutingMatrix};
+use anyhow::Context;
+use serde::{Deserialize, Deserializer};
+use std::{fmt, path::Path, str::FromStr};
+
+/// The settings of a config file. Fields left out keep their default.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SettingsFile {
+    /// Channel routing in the syntax of [`RoutingMatrix`].
+    #[serde(default, deserialize_with = "parsed")]
+    pub route: Option<RoutingMatrix>,
+    /// EQ bands in the syntax of [`EqSettings`].
+    #[serde(default, deserialize_with = "parsed")]
+    pub eq: Option<EqSettings>,
+}
+
+impl SettingsFile {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("could not read config file '{}'", path.display()))?;
+        text.parse()
+            .with_context(|| format!("invalid config file '{}'", path.display()))
+    }
+}
+
+impl FromStr for SettingsFile {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(serde_json::from_str(s)?)
+    }
+}
+
+/// Deserializes a string field with the [`FromStr`] implementation the command line uses.
+fn parsed<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let s = String::deserialize(deserializer)?;
+    s.parse()
+        .map(Some)
+        .map_err(|err| serde::de::Error::custom(format!("{:#}", err)))
+}
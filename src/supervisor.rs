@@ -0,0 +1,266 @@
+use crate::{
+    backend::Backend,
+    device::{find_input_device, find_output_device},
+    route::{InvalidSettings, Route, Sidetone},
+};
+use std::{
+    sync::mpsc::{Receiver, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+use tracing::{debug, info, warn};
+
+/// How often [`Supervisor::run`] checks the route for errors.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// When and how a [`Supervisor`] rebuilds a failed route.
+#[derive(Debug, Clone)]
+pub struct RecoveryPolicy {
+    /// Delay before the first rebuild attempt after a failure.
+    pub initial_backoff: Duration,
+    /// Upper bound for the delay, which doubles after every failed attempt.
+    pub max_backoff: Duration,
+    /// Give up after this many consecutive failed attempts; `None` retries forever.
+    pub max_retries: Option<u32>,
+    /// Run on the default devices while the configured ones are unavailable.
+    pub fallback_to_default: bool,
+    /// How often a route on the default devices checks whether the configured ones came back.
+    pub preferred_check_interval: Duration,
+    /// Backend-specific stream errors tolerated per second before the route is rebuilt.
+    pub error_budget: u64,
+}
+
+impl Default for RecoveryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            max_retries: None,
+            fallback_to_default: false,
+            preferred_check_interval: Duration::from_secs(1),
+            error_budget: 10,
+        }
+    }
+}
+
+impl RecoveryPolicy {
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2_u32.saturating_pow(attempt))
+            .min(self.max_backoff)
+    }
+}
+
+enum State {
+    Waiting {
+        until: Instant,
+        attempt: u32,
+    },
+    Running {
+        route: Route,
+        fallback: bool,
+        window_start: Instant,
+        window_errors: u64,
+        preferred_check: Instant,
+    },
+}
+
+/// Keeps a route running: watches it for fatal stream errors, tears it down, and rebuilds it
+/// with backoff once the devices are usable again. Settings the devices cannot satisfy, such as
+/// a latency shorter than one buffer, are returned as errors instead of retried.
+///
+/// Routes are built from a factory because a [`Sidetone`] builder, and its processors, is
+/// consumed by every build.
+pub struct Supervisor<B, F> {
+    backend: B,
+    factory: F,
+    policy: RecoveryPolicy,
+    state: State,
+    restarts: u64,
+    /// The route latency last logged, to report the changes made by the latency tuner.
+    latency: Duration,
+    /// The overrun and underrun counts last logged, to report the new ones.
+    overruns: u64,
+    underruns: u64,
+}
+
+impl<B: Backend, F: FnMut() -> Sidetone> Supervisor<B, F> {
+    pub fn new(backend: B, factory: F, policy: RecoveryPolicy) -> Self {
+        Self {
+            backend,
+            factory,
+            policy,
+            state: State::Waiting {
+                until: Instant::now(),
+                attempt: 0,
+            },
+            restarts: 0,
+            latency: Duration::ZERO,
+            overruns: 0,
+            underruns: 0,
+        }
+    }
+
+    /// The currently running route, if any.
+    pub fn route(&self) -> Option<&Route> {
+        match &self.state {
+            State::Running { route, .. } => Some(route),
+            State::Waiting { .. } => None,
+        }
+    }
+
+    /// Whether the running route uses the default devices instead of the configured ones.
+    pub fn is_on_fallback(&self) -> bool {
+        matches!(self.state, State::Running { fallback: true, .. })
+    }
+
+    /// How many times the route was torn down and rebuilt.
+    pub fn restarts(&self) -> u64 {
+        self.restarts
+    }
+
+    /// Advances the supervisor without blocking: builds the route when a retry is due, and tears
+    /// it down when it has failed. Fails only when the retry limit is exhausted.
+    pub fn poll(&mut self) -> anyhow::Result<()> {
+        let now = Instant::now();
+        match &mut self.state {
+            State::Waiting { until, attempt } => {
+                if now >= *until {
+                    let attempt = *attempt;
+                    self.try_build(now, attempt)?;
+                }
+            }
+            State::Running {
+                route,
+                fallback,
+                window_start,
+                window_errors,
+                preferred_check,
+            } => {
+                let stats = route.stats();
+                let current = route.latency();
+                if current != self.latency {
+                    info!(
+                        "Latency {} to {:.1} ms",
+                        if current < self.latency {
+                            "lowered"
+                        } else {
+                            "raised"
+                        },
+                        current.as_secs_f64() * 1e3
+                    );
+                    self.latency = current;
+                }
+                if stats.overruns != self.overruns {
+                    debug!("output stream fell behind: try increasing latency");
+                    self.overruns = stats.overruns;
+                }
+                if stats.underruns != self.underruns {
+                    debug!("input stream fell behind: try increasing latency");
+                    self.underruns = stats.underruns;
+                }
+                if now.duration_since(*window_start) >= Duration::from_secs(1) {
+                    *window_start = now;
+                    *window_errors = stats.stream_errors;
+                }
+                let errors = stats.stream_errors - *window_errors;
+                if stats.device_lost || errors > self.policy.error_budget {
+                    if stats.device_lost {
+                        warn!("device lost, tearing down the route");
+                    } else {
+                        warn!(
+                            "{} stream errors within a second, tearing down the route",
+                            errors
+                        );
+                    }
+                    self.restart(now + self.policy.initial_backoff);
+                } else if *fallback && now >= *preferred_check {
+                    *preferred_check = now + self.policy.preferred_check_interval;
+                    if self.preferred_available() {
+                        info!("configured devices are back, leaving the fallback route");
+                        // Fade out the fallback route instead of cutting it off mid-buffer.
+                        if let State::Running { route, .. } = &self.state {
+                            if let Err(err) = route.shutdown(false) {
+                                warn!("{:#}", err);
+                            }
+                        }
+                        self.restart(now);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Polls until a message arrives on `stop` or the retry limit is exhausted.
+    pub fn run(&mut self, stop: &Receiver<()>) -> anyhow::Result<()> {
+        loop {
+            self.poll()?;
+            match stop.recv_timeout(POLL_INTERVAL) {
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+        }
+    }
+
+    fn restart(&mut self, until: Instant) {
+        self.restarts += 1;
+        // Dropping the old state closes its streams before anything is reopened.
+        self.state = State::Waiting { until, attempt: 0 };
+    }
+
+    fn try_build(&mut self, now: Instant, attempt: u32) -> anyhow::Result<()> {
+        let sidetone = (self.factory)();
+        let (result, fallback) = match self.build(sidetone) {
+            Ok(route) => (Ok(route), false),
+            // Settings the devices cannot satisfy fail the same way on every attempt.
+            Err(err) if err.downcast_ref::<InvalidSettings>().is_some() => return Err(err),
+            Err(err) if self.policy.fallback_to_default => {
+                warn!("{:#}, falling back to the default devices", err);
+                let sidetone = (self.factory)()
+                    .input_device("default")
+                    .output_device("default");
+                (self.build(sidetone), true)
+            }
+            Err(err) => (Err(err), false),
+        };
+        match result {
+            Ok(route) => {
+                self.latency = route.latency();
+                self.overruns = 0;
+                self.underruns = 0;
+                self.state = State::Running {
+                    route,
+                    fallback,
+                    window_start: now,
+                    window_errors: 0,
+                    preferred_check: now + self.policy.preferred_check_interval,
+                };
+            }
+            Err(err) if err.downcast_ref::<InvalidSettings>().is_some() => return Err(err),
+            Err(err) => {
+                if self.policy.max_retries.is_some_and(|x| attempt >= x) {
+                    return Err(err.context(format!("giving up after {} retries", attempt)));
+                }
+                let backoff = self.policy.backoff(attempt);
+                warn!("{:#}, retrying in {:?}", err, backoff);
+                self.state = State::Waiting {
+                    until: now + backoff,
+                    attempt: attempt + 1,
+                };
+            }
+        }
+        Ok(())
+    }
+
+    fn build(&self, sidetone: Sidetone) -> anyhow::Result<Route> {
+        let route = sidetone.build(&self.backend)?;
+        route.start()?;
+        Ok(route)
+    }
+
+    fn preferred_available(&mut self) -> bool {
+        let sidetone = (self.factory)();
+        find_input_device(sidetone.input_selector(), &self.backend).is_ok()
+            && find_output_device(sidetone.output_selector(), &self.backend).is_ok()
+    }
+}
@@ -0,0 +1,94 @@
+//! Finds the lowest buffer level at which the route plays without glitches.
+
+/// Glitch-free time required before the target is lowered, in seconds.
+const INITIAL_HOLD_SECONDS: f64 = 10.0;
+/// The hold time doubles whenever a lowered target glitched, up to this many seconds.
+const MAX_HOLD_SECONDS: f64 = 320.0;
+
+/// A change of the buffer level target, in samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Adjustment {
+    Unchanged,
+    Raised(usize),
+    Lowered(usize),
+}
+
+/// Raises the target quickly after every glitch and lowers it one small step at a time after
+/// long glitch-free stretches. A step down that glitches doubles the wait before the next one, so
+/// the target settles just above the lowest glitch-free level instead of oscillating around it.
+pub(crate) struct LatencyTuner {
+    target: usize,
+    /// The target never drops below what one callback of either stream moves.
+    floor: usize,
+    ceiling: usize,
+    step: usize,
+    hold: f64,
+    stable: f64,
+    /// Whether the last change lowered the target and has not been glitch-free for a hold yet.
+    probation: bool,
+}
+
+impl LatencyTuner {
+    /// Starts at `floor` samples and never exceeds `ceiling`; lowers the target by `step`.
+    pub(crate) fn new(floor: usize, ceiling: usize, step: usize) -> Self {
+        let floor = floor.min(ceiling);
+        Self {
+            target: floor,
+            floor,
+            ceiling,
+            step: step.max(1),
+            hold: INITIAL_HOLD_SECONDS,
+            stable: 0.0,
+            probation: false,
+        }
+    }
+
+    /// The buffer level to hold, in samples.
+    pub(crate) fn target(&self) -> usize {
+        self.target
+    }
+
+    /// Raises the lower bound once the callback sizes are known.
+    pub(crate) fn raise_floor(&mut self, floor: usize) -> Adjustment {
+        self.floor = self.floor.max(floor.min(self.ceiling));
+        if self.target >= self.floor {
+            return Adjustment::Unchanged;
+        }
+        let delta = self.floor - self.target;
+        self.target = self.floor;
+        Adjustment::Raised(delta)
+    }
+
+    /// Feeds whether a callback covering `seconds` of audio glitched.
+    pub(crate) fn update(&mut self, glitch: bool, seconds: f64) -> Adjustment {
+        if glitch {
+            if self.probation {
+                self.hold = (self.hold * 2.0).min(MAX_HOLD_SECONDS);
+            }
+            self.probation = false;
+            self.stable = 0.0;
+            let raised = (self.target + (self.target / 4).max(self.step)).min(self.ceiling);
+            let delta = raised - self.target;
+            self.target = raised;
+            return if delta > 0 {
+                Adjustment::Raised(delta)
+            } else {
+                Adjustment::Unchanged
+            };
+        }
+        self.stable += seconds;
+        if self.stable < self.hold {
+            return Adjustment::Unchanged;
+        }
+        self.stable = 0.0;
+        let lowered = self.target.saturating_sub(self.step).max(self.floor);
+        let delta = self.target - lowered;
+        self.target = lowered;
+        self.probation = delta > 0;
+        if delta > 0 {
+            Adjustment::Lowered(delta)
+        } else {
+            Adjustment::Unchanged
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use sidetone::{
+    backend::mock::MockDevice,
+    compressor::{Compressor, CompressorSettings},
+    MockBackend, Processor, Sidetone,
+};
+use std::time::Duration;
+
+const RATE: u32 = 48_000;
+/// Frames per millisecond.
+const MS: usize = 48;
+
+fn compressor(settings: CompressorSettings) -> Compressor {
+    let mut compressor = Compressor::new(settings);
+    compressor.prepare(RATE, 1);
+    compressor
+}
+
+/// A hard-knee 4:1 compressor above -20 dBFS that reacts within a frame.
+fn instant() -> CompressorSettings {
+    CompressorSettings {
+        threshold_db: -20.0,
+        ratio: 4.0,
+        knee_db: 0.0,
+        attack: Duration::ZERO,
+        release: Duration::ZERO,
+        makeup_db: 0.0,
+        lookahead: Duration::ZERO,
+    }
+}
+
+fn db(x: f32) -> f32 {
+    20.0 * x.abs().log10()
+}
+
+#[test]
+fn levels_above_the_threshold_are_divided_by_the_ratio() {
+    let mut compressor = compressor(instant());
+    for (input, expected) in [(-40.0, -40.0), (-20.0, -20.0), (0.0, -15.0), (-8.0, -17.0)] {
+        let mut block = [10_f32.powf(input / 20.0)];
+        compressor.process(&mut block);
+        assert!((db(block[0]) - expected).abs() < 1e-3, "{}", input);
+    }
+}
+
+#[test]
+fn soft_knee_bends_the_curve_around_the_threshold() {
+    let mut compressor = compressor(CompressorSettings {
+        knee_db: 10.0,
+        makeup_db: 6.0,
+        ..instant()
+    });
+    let mut output = |level: f32| {
+        let mut block = [10_f32.powf(level / 20.0)];
+        compressor.process(&mut block);
+        db(block[0]) - 6.0
+    };
+    // At the threshold half of the knee is already compressed: (1/4 - 1) * 5^2 / 20.
+    assert!((output(-20.0) - (-20.0 - 0.9375)).abs() < 1e-3);
+    // The knee ends where the hard curve starts.
+    assert!((output(-25.0) + 25.0).abs() < 1e-3);
+    assert!((output(-15.0) - (-20.0 + 5.0 / 4.0)).abs() < 1e-3);
+}
+
+#[test]
+fn gain_reduction_follows_attack_and_release() {
+    let mut compressor = compressor(CompressorSettings {
+        attack: Duration::from_millis(5),
+        release: Duration::from_millis(50),
+        ..instant()
+    });
+    let mut loud = vec![1.0; 50 * MS];
+    compressor.process(&mut loud);
+    // One time constant in, 63% of the 15 dB of reduction is reached.
+    assert!((db(loud[5 * MS]) + 15.0 * 0.632).abs() < 0.1);
+    assert!((compressor.reduction_db() + 15.0).abs() < 0.01);
+
+    let mut quiet = vec![0.01; 50 * MS];
+    compressor.process(&mut quiet);
+    assert!((db(quiet[50 * MS - 1] / 0.01) + 15.0 * 0.368).abs() < 0.1);
+}
+
+#[test]
+fn lookahead_delays_the_signal_and_the_route() -> anyhow::Result<()> {
+    let settings = CompressorSettings {
+        release: Duration::from_secs(1),
+        lookahead: Duration::from_millis(2),
+        ..instant()
+    };
+    let mut compressor = compressor(settings.clone());
+    assert_eq!(compressor.latency(), 2 * MS);
+    let mut block = vec![0.0; 4 * MS];
+    block[0] = 1.0;
+    compressor.process(&mut block);
+    // The peak arrives after the gain has already come down for it.
+    assert_eq!(block.iter().position(|&x| x != 0.0), Some(2 * MS));
+    assert!((db(block[2 * MS]) + 15.0).abs() < 0.05);
+
+    let backend = MockBackend::new()
+        .with_input_device(MockDevice::new("mic", 1, RATE))
+        .with_output_device(MockDevice::new("headphones", 1, RATE));
+    let route = Sidetone::new()
+        .latency(Duration::from_millis(20))
+        .processor(Compressor::new(settings))
+        .build(&backend)?;
+    assert_eq!(route.latency(), Duration::from_millis(22));
+    Ok(())
+}
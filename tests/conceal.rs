@@ -0,0 +1,171 @@
+use sidetone::{
+    backend::mock::MockDevice,
+    conceal::{Concealment, OverflowPolicy},
+    MockBackend, Route, Sidetone,
+};
+use std::time::Duration;
+
+const RATE: u32 = 1000;
+const PERIOD: usize = 20;
+
+fn backend() -> MockBackend {
+    MockBackend::new()
+        .with_input_device(MockDevice::new("mic", 1, RATE))
+        .with_output_device(MockDevice::new("headphones", 1, RATE))
+        .with_period(PERIOD)
+}
+
+fn ramp(len: usize) -> Vec<f32> {
+    (1..=len).map(|x| x as f32).collect()
+}
+
+/// Plays 200 frames of `input` after 100 frames of priming, then lets the input run dry.
+fn underrun(concealment: Concealment, input: &[f32]) -> anyhow::Result<Vec<f32>> {
+    let backend = backend();
+    let route = Sidetone::new()
+        .latency(Duration::from_millis(100))
+        .fade_in(Duration::ZERO)
+        .concealment(concealment)
+        .build(&backend)?;
+    route.start()?;
+    backend.push_input_blocks(input, PERIOD);
+    backend.run(25);
+    assert!(route.stats().underruns > 0);
+    Ok(backend.captured_output())
+}
+
+#[test]
+fn silence_fades_out_the_last_sample() -> anyhow::Result<()> {
+    let output = underrun(Concealment::Silence, &[0.5; 200])?;
+
+    assert!(output[100..300].iter().all(|&x| x == 0.5));
+    let fade = [0.5, 0.4, 0.3, 0.2, 0.1];
+    for (&x, y) in output[300..305].iter().zip(fade) {
+        assert!((x - y).abs() < 1e-6, "{:?}", &output[300..305]);
+    }
+    assert!(output[305..].iter().all(|&x| x == 0.0));
+    Ok(())
+}
+
+#[test]
+fn repeat_decays_the_last_block() -> anyhow::Result<()> {
+    let input = ramp(200);
+    let output = underrun(Concealment::Repeat, &input)?;
+
+    assert_eq!(output[100..300], input[..]);
+    for (k, &x) in output[300..360].iter().enumerate() {
+        let expected = input[180 + k % PERIOD] * (1.0 - k as f32 / 60.0);
+        assert!(
+            (x - expected).abs() < 1e-3,
+            "frame {}: {} != {}",
+            k,
+            x,
+            expected
+        );
+    }
+    assert!(output[360..].iter().all(|&x| x == 0.0));
+    Ok(())
+}
+
+#[test]
+fn continuation_follows_the_pitch_period() -> anyhow::Result<()> {
+    let sine = |n: usize| (n as f32 * std::f32::consts::TAU / 8.0).sin() * 0.5;
+    let input: Vec<f32> = (0..200).map(sine).collect();
+    let output = underrun(Concealment::Continuation, &input)?;
+
+    for (k, &x) in output[300..360].iter().enumerate() {
+        let expected = sine(200 + k) * (1.0 - k as f32 / 60.0);
+        assert!(
+            (x - expected).abs() < 1e-3,
+            "frame {}: {} != {}",
+            k,
+            x,
+            expected
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn resumed_input_is_crossfaded_from_the_concealment() -> anyhow::Result<()> {
+    let backend = backend();
+    let route = Sidetone::new()
+        .latency(Duration::from_millis(100))
+        .fade_in(Duration::ZERO)
+        .build(&backend)?;
+    route.start()?;
+    backend.run(6);
+    backend.push_input([0.5; PERIOD]);
+    backend.run_cycle();
+
+    let output = backend.captured_output();
+    let resumed = &output[output.len() - PERIOD..];
+    assert!(
+        resumed[..5].windows(2).all(|x| x[0] < x[1]),
+        "{:?}",
+        resumed
+    );
+    assert!(resumed[0] < 0.5);
+    assert!(resumed[5..].iter().all(|&x| x == 0.5));
+    Ok(())
+}
+
+fn overflow(policy: OverflowPolicy) -> anyhow::Result<(MockBackend, Route)> {
+    let backend = backend();
+    let route = Sidetone::new()
+        .latency(Duration::from_millis(100))
+        .fade_in(Duration::ZERO)
+        .buffer_frames(PERIOD as u32)
+        .overflow(policy)
+        .build(&backend)?;
+    route.start()?;
+    // Far more than the buffer holds arrives at once.
+    backend.push_input(ramp(500));
+    backend.run_cycle();
+    assert_eq!(route.stats().overruns, 1);
+    Ok((backend, route))
+}
+
+#[test]
+fn drop_newest_keeps_the_buffered_audio() -> anyhow::Result<()> {
+    let (backend, _route) = overflow(OverflowPolicy::DropNewest)?;
+    backend.run(11);
+
+    let output = backend.captured_output();
+    assert!(output[..100].iter().all(|&x| x == 0.0));
+    assert_eq!(output[100..240], ramp(140)[..]);
+    Ok(())
+}
+
+#[test]
+fn drop_oldest_returns_to_the_latency_target() -> anyhow::Result<()> {
+    let (backend, _route) = overflow(OverflowPolicy::DropOldest)?;
+    backend.run(4);
+
+    assert_eq!(backend.captured_output(), ramp(500)[400..].to_vec());
+    Ok(())
+}
+
+#[test]
+fn drop_oldest_never_plays_audio_older_than_what_it_dropped() -> anyhow::Result<()> {
+    let backend = backend();
+    let route = Sidetone::new()
+        .latency(Duration::from_millis(100))
+        .fade_in(Duration::ZERO)
+        .buffer_frames(PERIOD as u32)
+        .overflow(OverflowPolicy::DropOldest)
+        .build(&backend)?;
+    route.start()?;
+    // The ring of 240 samples holds the 100 primed ones and the first block, leaving room for only
+    // the last 20 frames of the second.
+    let input = ramp(180);
+    backend.push_input(&input[..120]);
+    backend.run_input();
+    backend.push_input(&input[120..]);
+    backend.run_cycle();
+    assert_eq!(route.stats().overruns, 1);
+
+    let output = backend.captured_output();
+    assert_eq!(output, input[160..]);
+    Ok(())
+}
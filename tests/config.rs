@@ -0,0 +1,136 @@
+use sidetone::{
+    backend::mock::MockDevice,
+    config::{negotiate, parse_sample_format, StreamRequest},
+    MockBackend, Sidetone,
+};
+use std::time::Duration;
+
+fn range(
+    channels: u16,
+    min_rate: u32,
+    max_rate: u32,
+    format: cpal::SampleFormat,
+) -> cpal::SupportedStreamConfigRange {
+    cpal::SupportedStreamConfigRange::new(
+        channels,
+        cpal::SampleRate(min_rate),
+        cpal::SampleRate(max_rate),
+        cpal::SupportedBufferSize::Range { min: 32, max: 1024 },
+        format,
+    )
+}
+
+/// A stereo interface defaulting to mono at 48 kHz that also runs stereo at 8 to 48 kHz.
+fn backend() -> MockBackend {
+    MockBackend::new()
+        .with_input_device(
+            MockDevice::new("interface", 1, 48_000).with_supported_config(2, 8_000, 48_000),
+        )
+        .with_output_device(MockDevice::new("headphones", 2, 48_000))
+}
+
+#[test]
+fn device_defaults_are_kept_without_a_request() -> anyhow::Result<()> {
+    let route = Sidetone::new().build(&backend())?;
+    let config = route.input_config();
+    assert_eq!(config.config.channels, 1);
+    assert_eq!(config.config.sample_rate.0, 48_000);
+    assert_eq!(config.sample_format, cpal::SampleFormat::F32);
+    assert_eq!(config.buffer_frames(), None);
+    Ok(())
+}
+
+#[test]
+fn supported_requests_are_met_exactly() -> anyhow::Result<()> {
+    let route = Sidetone::new()
+        .input_config(StreamRequest {
+            sample_rate: Some(16_000),
+            channels: Some(2),
+            buffer_frames: Some(256),
+            ..Default::default()
+        })
+        .build(&backend())?;
+    let config = route.input_config();
+    assert_eq!(config.config.channels, 2);
+    assert_eq!(config.config.sample_rate.0, 16_000);
+    assert_eq!(config.buffer_frames(), Some(256));
+    assert_eq!(
+        config.to_string(),
+        "16000 Hz, 2 channels, f32, 256 frame buffer"
+    );
+    Ok(())
+}
+
+#[test]
+fn unsupported_requests_get_the_nearest_config() -> anyhow::Result<()> {
+    let route = Sidetone::new()
+        .input_config(StreamRequest {
+            sample_rate: Some(96_000),
+            channels: Some(4),
+            sample_format: Some(cpal::SampleFormat::I16),
+            buffer_frames: None,
+        })
+        .build(&backend())?;
+    let config = route.input_config();
+    assert_eq!(config.config.channels, 2);
+    assert_eq!(config.config.sample_rate.0, 48_000);
+    assert_eq!(config.sample_format, cpal::SampleFormat::F32);
+    Ok(())
+}
+
+#[test]
+fn per_side_buffer_size_overrides_the_shared_one() -> anyhow::Result<()> {
+    let route = Sidetone::new()
+        .latency(Duration::from_secs(1))
+        .buffer_frames(64)
+        .output_config(StreamRequest {
+            buffer_frames: Some(128),
+            ..Default::default()
+        })
+        .build(&backend())?;
+    assert_eq!(route.input_config().buffer_frames(), Some(64));
+    assert_eq!(route.output_config().buffer_frames(), Some(128));
+    Ok(())
+}
+
+#[test]
+fn requested_fields_outrank_the_defaults() -> anyhow::Result<()> {
+    let default = cpal::SupportedStreamConfig::new(
+        2,
+        cpal::SampleRate(48_000),
+        cpal::SupportedBufferSize::Range { min: 32, max: 1024 },
+        cpal::SampleFormat::F32,
+    );
+    let supported = [
+        range(2, 48_000, 48_000, cpal::SampleFormat::F32),
+        range(2, 44_100, 44_100, cpal::SampleFormat::I16),
+        range(1, 48_000, 48_000, cpal::SampleFormat::I16),
+    ];
+    // Only the format is asked for; of the defaults the sample rate is kept before the channel
+    // count.
+    let config = negotiate(
+        &supported,
+        &default,
+        &StreamRequest {
+            sample_format: Some(cpal::SampleFormat::I16),
+            ..Default::default()
+        },
+    )?;
+    assert_eq!(config.sample_format, cpal::SampleFormat::I16);
+    assert_eq!(config.config.channels, 1);
+    assert_eq!(config.config.sample_rate.0, 48_000);
+    Ok(())
+}
+
+#[test]
+fn sample_formats_parse_by_their_cpal_names() -> anyhow::Result<()> {
+    for format in [
+        cpal::SampleFormat::I16,
+        cpal::SampleFormat::U8,
+        cpal::SampleFormat::F32,
+    ] {
+        assert_eq!(parse_sample_format(&format.to_string())?, format);
+    }
+    assert!(parse_sample_format("s16le").is_err());
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use sidetone::{
+    backend::mock::MockDevice,
+    device::{find_input_device, DeviceSelector},
+    MockBackend,
+};
+
+fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|x| x.to_string()).collect()
+}
+
+fn select(selector: &str, devices: &[&str]) -> anyhow::Result<usize> {
+    selector.parse::<DeviceSelector>()?.select(&names(devices))
+}
+
+const ALSA: [&str; 6] = [
+    "default",
+    "sysdefault:CARD=PCH",
+    "front:CARD=PCH,DEV=0",
+    "sysdefault:CARD=Headset",
+    "front:CARD=Headset,DEV=0",
+    "plughw:CARD=Headset,DEV=0",
+];
+
+#[test]
+fn exact_name_wins_over_substring() -> anyhow::Result<()> {
+    assert_eq!(select("Mic", &["USB Mic Pro", "Mic"])?, 1);
+    Ok(())
+}
+
+#[test]
+fn substring_ignores_case() -> anyhow::Result<()> {
+    assert_eq!(select("usb", &["Built-in", "Jabra USB Headset"])?, 1);
+    Ok(())
+}
+
+#[test]
+fn index_selects_list_position() -> anyhow::Result<()> {
+    assert_eq!(select("2", &ALSA)?, 2);
+    assert!(select("6", &ALSA).is_err());
+    Ok(())
+}
+
+#[test]
+fn regex_selects_matching_name() -> anyhow::Result<()> {
+    assert_eq!(select("re:^front:.*Headset", &ALSA)?, 4);
+    assert!(select("re:[", &ALSA).is_err());
+    Ok(())
+}
+
+#[test]
+fn card_selects_by_alsa_identifier() -> anyhow::Result<()> {
+    assert_eq!(select("card:headset", &ALSA)?, 5);
+    assert_eq!(select("card:PCH,0", &ALSA)?, 2);
+    assert!(select("card:PCH,1", &ALSA).is_err());
+    Ok(())
+}
+
+#[test]
+fn ambiguous_match_lists_candidates() {
+    let err = select("headset", &ALSA).map_err(|x| x.to_string());
+    assert_eq!(
+        err,
+        Err(String::from(
+            "'headset' is ambiguous; it matches 3. sysdefault:CARD=Headset, \
+             4. front:CARD=Headset,DEV=0, 5. plughw:CARD=Headset,DEV=0"
+        ))
+    );
+}
+
+#[test]
+fn backend_devices_are_selected() -> anyhow::Result<()> {
+    let backend = MockBackend::new()
+        .with_input_device(MockDevice::new("Built-in Microphone", 1, 48_000))
+        .with_input_device(MockDevice::new("Jabra USB Headset", 1, 16_000));
+    assert_eq!(
+        find_input_device("default", &backend)?.name,
+        "Built-in Microphone"
+    );
+    assert_eq!(
+        find_input_device("jabra", &backend)?.name,
+        "Jabra USB Headset"
+    );
+    assert_eq!(find_input_device("1", &backend)?.name, "Jabra USB Headset");
+    Ok(())
+}
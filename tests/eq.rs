@@ -0,0 +1,107 @@
+use sidetone::{
+    backend::mock::MockDevice,
+    eq::{BandKind, EqSettings, Equalizer, DEFAULT_Q},
+    MockBackend, Processor, Sidetone,
+};
+use std::time::Duration;
+
+const RATE: u32 = 48_000;
+
+/// The gain in dB of `equalizer` at `frequency`, measured on a settled sine.
+fn response(equalizer: &mut Equalizer, sample_rate: u32, frequency: f32) -> f32 {
+    equalizer.reset();
+    let mut block: Vec<f32> = (0..sample_rate as usize / 2)
+        .map(|x| (x as f32 * frequency / sample_rate as f32 * std::f32::consts::TAU).sin())
+        .collect();
+    equalizer.process(&mut block);
+    let settled = &block[block.len() / 2..];
+    let rms = (settled.iter().map(|x| x * x).sum::<f32>() / settled.len() as f32).sqrt();
+    20.0 * (rms * std::f32::consts::SQRT_2).log10()
+}
+
+fn equalizer(settings: &str, sample_rate: u32) -> anyhow::Result<Equalizer> {
+    let mut equalizer = Equalizer::new(settings.parse()?);
+    equalizer.prepare(sample_rate, 1);
+    Ok(equalizer)
+}
+
+#[test]
+fn bands_shape_the_response() -> anyhow::Result<()> {
+    let mut peak = equalizer("peak:1000:6:2", RATE)?;
+    assert!((response(&mut peak, RATE, 1000.0) - 6.0).abs() < 0.1);
+    assert!(response(&mut peak, RATE, 100.0).abs() < 0.1);
+
+    let mut shelves = equalizer("lowshelf:200:-6,highshelf:5000:4", RATE)?;
+    assert!((response(&mut shelves, RATE, 40.0) + 6.0).abs() < 0.2);
+    assert!(response(&mut shelves, RATE, 1000.0).abs() < 0.5);
+    assert!((response(&mut shelves, RATE, 16000.0) - 4.0).abs() < 0.2);
+
+    let mut band = equalizer("highpass:200,lowpass:4000", RATE)?;
+    assert!((response(&mut band, RATE, 200.0) + 3.0).abs() < 0.2);
+    assert!(response(&mut band, RATE, 50.0) < -20.0);
+    assert!(response(&mut band, RATE, 1000.0).abs() < 0.5);
+    assert!(response(&mut band, RATE, 16000.0) < -20.0);
+
+    let mut notch = equalizer("notch:50:4", RATE)?;
+    assert!(response(&mut notch, RATE, 50.0) < -30.0);
+    assert!(response(&mut notch, RATE, 200.0).abs() < 0.2);
+    Ok(())
+}
+
+#[test]
+fn coefficients_follow_the_sample_rate() -> anyhow::Result<()> {
+    let mut equalizer = equalizer("peak:1000:-9", RATE)?;
+    assert!((response(&mut equalizer, RATE, 1000.0) + 9.0).abs() < 0.1);
+    equalizer.prepare(16_000, 1);
+    assert!((response(&mut equalizer, 16_000, 1000.0) + 9.0).abs() < 0.1);
+    Ok(())
+}
+
+#[test]
+fn settings_parse_and_print_back() -> anyhow::Result<()> {
+    let settings: EqSettings = "highpass:80, peak:250:-4:1.5,highshelf:6000:3".parse()?;
+    assert_eq!(
+        settings,
+        EqSettings::new()
+            .band(BandKind::HighPass, 80.0, 0.0, DEFAULT_Q)
+            .band(BandKind::Peak, 250.0, -4.0, 1.5)
+            .band(BandKind::HighShelf, 6000.0, 3.0, DEFAULT_Q)
+    );
+    assert_eq!(
+        settings.to_string(),
+        "highpass:80,peak:250:-4:1.5,highshelf:6000:3"
+    );
+    for invalid in [
+        "bell:1000:3",
+        "peak:1000",
+        "notch:-50",
+        "lowpass:8000:0",
+        "notch:50:4:1",
+    ] {
+        assert!(invalid.parse::<EqSettings>().is_err(), "{}", invalid);
+    }
+    Ok(())
+}
+
+#[test]
+fn equalizer_runs_on_every_output_channel() -> anyhow::Result<()> {
+    let backend = MockBackend::new()
+        .with_input_device(MockDevice::new("mic", 1, RATE))
+        .with_output_device(MockDevice::new("headphones", 2, RATE));
+    let route = Sidetone::new()
+        .latency(Duration::from_millis(10))
+        .fade_in(Duration::ZERO)
+        .processor(Equalizer::new("lowshelf:1000:-6".parse()?))
+        .build(&backend)?;
+    route.start()?;
+    backend.push_input_blocks(&[0.5; 9600], 480);
+    backend.run(20);
+
+    // A constant level sits deep in the low shelf.
+    let output = backend.captured_output();
+    let half = 0.5 * 10_f32.powf(-6.0 / 20.0);
+    assert!(output[output.len() - 2..]
+        .iter()
+        .all(|x| (x - half).abs() < 1e-3));
+    Ok(())
+}
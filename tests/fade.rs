@@ -0,0 +1,122 @@
+use sidetone::{backend::mock::MockDevice, MockBackend, Route, Sidetone};
+use std::time::Duration;
+
+const RATE: u32 = 1000;
+const PERIOD: usize = 20;
+
+fn backend() -> MockBackend {
+    MockBackend::new()
+        .with_input_device(MockDevice::new("mic", 1, RATE))
+        .with_output_device(MockDevice::new("headphones", 1, RATE))
+        .with_period(PERIOD)
+}
+
+/// A route primed with 100 frames of silence that fades over 10 frames.
+fn route(backend: &MockBackend) -> anyhow::Result<Route> {
+    let route = Sidetone::new()
+        .latency(Duration::from_millis(100))
+        .fade_in(Duration::from_millis(10))
+        .fade_out(Duration::from_millis(10))
+        .build(backend)?;
+    route.start()?;
+    Ok(route)
+}
+
+fn assert_close(actual: &[f32], expected: &[f32]) {
+    assert_eq!(actual.len(), expected.len());
+    for (x, y) in actual.iter().zip(expected) {
+        assert!((x - y).abs() < 1e-6, "{:?} != {:?}", actual, expected);
+    }
+}
+
+/// Equal-power gains of a 10 frame ramp up.
+fn rise() -> Vec<f32> {
+    (1..=10)
+        .map(|x| (x as f32 / 10.0 * std::f32::consts::FRAC_PI_2).sin())
+        .collect()
+}
+
+#[test]
+fn first_input_fades_in_after_the_primed_silence() -> anyhow::Result<()> {
+    let backend = backend();
+    let _route = route(&backend)?;
+    backend.push_input_blocks(&[1.0; 100], PERIOD);
+    backend.run(10);
+
+    let output = backend.captured_output();
+    assert!(output[..100].iter().all(|&x| x == 0.0));
+    assert_close(&output[100..110], &rise());
+    assert!(output[110..].iter().all(|&x| x == 1.0));
+    Ok(())
+}
+
+#[test]
+fn mute_and_unmute_ramp_with_equal_power() -> anyhow::Result<()> {
+    let backend = backend();
+    let route = route(&backend)?;
+    backend.push_input_blocks(&[1.0; 300], PERIOD);
+    backend.run(8);
+    route.set_muted(true);
+    backend.run(2);
+    assert!(route.is_muted());
+    assert!(route.is_silent());
+    route.set_muted(false);
+    backend.run(2);
+
+    let output = backend.captured_output();
+    let mut fall = rise();
+    fall.reverse();
+    assert_close(&output[160..169], &fall[1..]);
+    assert!(output[169..200].iter().all(|&x| x == 0.0));
+    assert_close(&output[200..210], &rise());
+    assert!(output[210..].iter().all(|&x| x == 1.0));
+    Ok(())
+}
+
+#[test]
+fn fade_out_without_drain_silences_the_buffered_audio() -> anyhow::Result<()> {
+    let backend = backend();
+    let route = route(&backend)?;
+    backend.push_input_blocks(&[1.0; 300], PERIOD);
+    backend.run(8);
+    route.fade_out(false);
+    backend.run(1);
+    assert!(route.is_silent());
+    backend.run(4);
+
+    let output = backend.captured_output();
+    assert!(output[169..].iter().all(|&x| x == 0.0));
+    Ok(())
+}
+
+#[test]
+fn drain_plays_the_buffer_before_fading_out() -> anyhow::Result<()> {
+    let backend = backend();
+    let route = route(&backend)?;
+    backend.push_input_blocks(&[1.0; 300], PERIOD);
+    backend.run(8);
+    route.fade_out(true);
+    // The 100 frames buffered when draining started are still played, the rest of the input
+    // is ignored.
+    backend.run(4);
+    assert!(!route.is_silent());
+    backend.run(1);
+    assert!(route.is_silent());
+    assert_eq!(route.stats().underruns, 0);
+
+    let output = backend.captured_output();
+    assert!(output[160..240].iter().all(|&x| x == 1.0));
+    assert!(output[240..250].windows(2).all(|x| x[0] > x[1]));
+    assert_eq!(output[249], 0.0);
+    Ok(())
+}
+
+#[test]
+fn shutdown_stops_a_silent_route() -> anyhow::Result<()> {
+    let backend = backend();
+    let route = route(&backend)?;
+    route.shutdown(true)?;
+    backend.run(1);
+    assert!(backend.captured_output().is_empty());
+    Ok(())
+}
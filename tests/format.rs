@@ -0,0 +1,75 @@
+use sidetone::{backend::mock::MockDevice, MockBackend, Sidetone};
+use std::time::Duration;
+
+const RATE: u32 = 1000;
+const PERIOD: usize = 100;
+/// One step of a 16-bit device.
+const LSB: f32 = 1.0 / 32768.0;
+
+fn backend(input: cpal::SampleFormat, output: cpal::SampleFormat) -> MockBackend {
+    MockBackend::new()
+        .with_input_device(MockDevice::new("headset mic", 1, RATE).with_sample_format(input))
+        .with_output_device(MockDevice::new("headset", 1, RATE).with_sample_format(output))
+        .with_period(PERIOD)
+}
+
+/// Plays `input` through a route with 100 ms of latency and returns what followed the priming.
+fn play(backend: &MockBackend, input: &[f32]) -> anyhow::Result<Vec<f32>> {
+    let route = Sidetone::new()
+        .latency(Duration::from_millis(100))
+        .fade_in(Duration::ZERO)
+        .build(backend)?;
+    route.start()?;
+    backend.push_input_blocks(input, PERIOD);
+    backend.run(input.len() / PERIOD + 1);
+    Ok(backend.captured_output()[PERIOD..].to_vec())
+}
+
+#[test]
+fn integer_devices_carry_the_signal_within_the_dither() -> anyhow::Result<()> {
+    let input: Vec<f32> = (0..1000).map(|x| (x as f32 * 0.05).sin() * 0.5).collect();
+    for (input_format, output_format) in [
+        (cpal::SampleFormat::I16, cpal::SampleFormat::I16),
+        (cpal::SampleFormat::U16, cpal::SampleFormat::I32),
+        (cpal::SampleFormat::I32, cpal::SampleFormat::U8),
+    ] {
+        let backend = backend(input_format, output_format);
+        let output = play(&backend, &input)?;
+        let step = match (input_format, output_format) {
+            (_, cpal::SampleFormat::U8) => 1.0 / 128.0,
+            _ => LSB,
+        };
+        for (x, y) in output.iter().zip(&input) {
+            assert!(
+                (x - y).abs() <= 2.0 * step,
+                "{} to {}: {} != {}",
+                input_format,
+                output_format,
+                x,
+                y
+            );
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn dither_keeps_signals_below_one_step_on_average() -> anyhow::Result<()> {
+    let backend = backend(cpal::SampleFormat::F32, cpal::SampleFormat::I16);
+    let output = play(&backend, &[LSB / 4.0; 10_000])?;
+
+    // Plain rounding would turn a quarter step into silence.
+    let mean = output.iter().sum::<f32>() / output.len() as f32;
+    assert!((mean / LSB - 0.25).abs() < 0.05, "{}", mean / LSB);
+    assert!(output.iter().all(|x| (x / LSB).fract() == 0.0));
+    assert!(output.iter().all(|x| x.abs() <= 2.0 * LSB));
+    Ok(())
+}
+
+#[test]
+fn full_scale_output_is_clamped_to_the_integer_range() -> anyhow::Result<()> {
+    let backend = backend(cpal::SampleFormat::F32, cpal::SampleFormat::I16);
+    let output = play(&backend, &[1.5; 200])?;
+    assert!(output.iter().all(|&x| x == 1.0 - LSB), "{:?}", &output[..4]);
+    Ok(())
+}
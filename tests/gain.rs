@@ -0,0 +1,78 @@
+use sidetone::{backend::mock::MockDevice, gain::MAX_GAIN_DB, MockBackend, Route, Sidetone};
+use std::time::Duration;
+
+const RATE: u32 = 1000;
+const PERIOD: usize = 20;
+
+fn backend() -> MockBackend {
+    MockBackend::new()
+        .with_input_device(MockDevice::new("mic", 1, RATE))
+        .with_output_device(MockDevice::new("headphones", 1, RATE))
+        .with_period(PERIOD)
+}
+
+/// Starts a route with 100 frames of priming and plays `level` through it for 300 frames.
+fn route(backend: &MockBackend, sidetone: Sidetone, level: f32) -> anyhow::Result<Route> {
+    let route = sidetone
+        .latency(Duration::from_millis(100))
+        .fade_in(Duration::ZERO)
+        .build(backend)?;
+    route.start()?;
+    backend.push_input_blocks(&[level; 300], PERIOD);
+    Ok(route)
+}
+
+#[test]
+fn initial_gain_applies_from_the_first_sample() -> anyhow::Result<()> {
+    let backend = backend();
+    let _route = route(&backend, Sidetone::new().gain_db(-20.0), 1.0)?;
+    backend.run(10);
+
+    let output = backend.captured_output();
+    assert!(output[100..].iter().all(|&x| (x - 0.1).abs() < 1e-6));
+    Ok(())
+}
+
+#[test]
+fn gain_changes_glide_without_steps() -> anyhow::Result<()> {
+    let backend = backend();
+    let route = route(&backend, Sidetone::new(), 1.0)?;
+    backend.run(6);
+    route.set_gain_db(-6.0);
+    backend.run(9);
+    assert!((route.gain_db() + 6.0).abs() < 1e-4);
+
+    let output = backend.captured_output();
+    let target = 10_f32.powf(-6.0 / 20.0);
+    assert!(output[120..].windows(2).all(|x| x[1] <= x[0]));
+    assert!(output[120..].windows(2).all(|x| x[0] - x[1] < 0.03));
+    assert!(output[120] > 0.95);
+    assert!((output[299] - target).abs() < 1e-4);
+    Ok(())
+}
+
+#[test]
+fn ceiling_bounds_the_output_whatever_the_gain() -> anyhow::Result<()> {
+    let backend = backend();
+    let route = route(
+        &backend,
+        Sidetone::new().gain_db(12.0).ceiling_db(-6.0),
+        0.5,
+    )?;
+    route.set_gain_db(100.0);
+    assert_eq!(route.gain_db(), MAX_GAIN_DB);
+    backend.run(15);
+
+    let ceiling = 10_f32.powf(-6.0 / 20.0);
+    let start = (route.latency().as_secs_f64() * RATE as f64).round() as usize;
+    let output = backend.captured_output();
+    assert!(output[start..].iter().all(|&x| x <= ceiling));
+    assert!(output[start + 100..].iter().all(|&x| ceiling - x < 1e-5));
+    Ok(())
+}
+
+#[test]
+fn ceiling_above_full_scale_is_rejected() {
+    let result = Sidetone::new().ceiling_db(3.0).build(&backend());
+    assert!(result.is_err());
+}
@@ -0,0 +1,127 @@
+use sidetone::{
+    gate::{GateSettings, NoiseGate},
+    Processor,
+};
+use std::time::Duration;
+
+const RATE: u32 = 48_000;
+/// Frames per millisecond.
+const MS: usize = 48;
+
+fn sine(frequency: f32, amplitude: f32, frames: usize) -> Vec<f32> {
+    (0..frames)
+        .map(|x| (x as f32 * frequency / RATE as f32 * std::f32::consts::TAU).sin() * amplitude)
+        .collect()
+}
+
+fn peak(samples: &[f32]) -> f32 {
+    samples.iter().fold(0.0, |peak, x| peak.max(x.abs()))
+}
+
+fn gate(settings: GateSettings) -> NoiseGate {
+    let mut gate = NoiseGate::new(settings);
+    gate.prepare(RATE, 1);
+    gate
+}
+
+/// A gate without a sidechain filter that opens at -20 dBFS and closes at -30 dBFS.
+fn settings() -> GateSettings {
+    GateSettings {
+        open_threshold_db: -20.0,
+        close_threshold_db: -30.0,
+        attack: Duration::from_millis(1),
+        hold: Duration::from_millis(50),
+        release: Duration::from_millis(10),
+        range_db: -40.0,
+        sidechain_highpass: None,
+    }
+}
+
+#[test]
+fn quiet_signals_are_attenuated_by_the_range() {
+    let mut gate = gate(settings());
+    let input = sine(1000.0, 0.05, 100 * MS);
+    let mut block = input.clone();
+    gate.process(&mut block);
+
+    assert!(!gate.is_open());
+    for (x, y) in block.iter().zip(&input) {
+        assert!((x - y * 0.01).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn loud_signals_open_the_gate_within_the_attack() {
+    let mut gate = gate(settings());
+    let input = sine(1000.0, 0.5, 20 * MS);
+    let mut block = input.clone();
+    gate.process(&mut block);
+
+    assert!(gate.is_open());
+    // The first sample above the threshold is a few frames in, the ramp takes 1 ms after it.
+    assert_eq!(block[2 * MS..], input[2 * MS..]);
+    assert!(peak(&block[..MS / 4]) < 0.5 * 0.3);
+}
+
+#[test]
+fn levels_between_the_thresholds_keep_the_gate_as_it_is() {
+    let mut gate = gate(settings());
+    let between = sine(1000.0, 0.06, 200 * MS);
+    let mut block = between.clone();
+    gate.process(&mut block);
+    assert!(!gate.is_open());
+
+    let mut block = sine(1000.0, 0.5, 10 * MS);
+    gate.process(&mut block);
+    let mut block = between.clone();
+    gate.process(&mut block);
+    assert!(gate.is_open());
+    assert_eq!(block, between);
+}
+
+#[test]
+fn gate_holds_open_then_releases() {
+    let mut gate = gate(settings());
+    let mut block = sine(1000.0, 0.5, 10 * MS);
+    gate.process(&mut block);
+
+    let mut silence = vec![0.01; 100 * MS];
+    gate.process(&mut silence);
+    // The detector takes a few ms to fall below the close threshold before the hold starts.
+    assert!(silence[..50 * MS].iter().all(|&x| x == 0.01));
+    assert!(silence[90 * MS..]
+        .iter()
+        .all(|&x| (x - 0.0001).abs() < 1e-7));
+    assert!(silence[50 * MS..90 * MS].windows(2).all(|x| x[1] <= x[0]));
+    assert!(!gate.is_open());
+}
+
+#[test]
+fn sidechain_highpass_keeps_rumble_from_opening_the_gate() {
+    let rumble = sine(20.0, 0.1, 500 * MS);
+    let mut unfiltered = gate(GateSettings {
+        open_threshold_db: -45.0,
+        close_threshold_db: -50.0,
+        ..settings()
+    });
+    let mut block = rumble.clone();
+    unfiltered.process(&mut block);
+    assert!(unfiltered.is_open());
+
+    let mut filtered = gate(GateSettings {
+        open_threshold_db: -45.0,
+        close_threshold_db: -50.0,
+        sidechain_highpass: Some(120.0),
+        ..settings()
+    });
+    let mut block = rumble.clone();
+    filtered.process(&mut block);
+    assert!(!filtered.is_open());
+    // Only the onset of the filter gets through, the steady rumble stays at the floor.
+    assert!(peak(&block[100 * MS..]) < 0.1 * 0.011);
+
+    // Speech-band signals at the same level still open it.
+    let mut block = sine(1000.0, 0.1, 10 * MS);
+    filtered.process(&mut block);
+    assert!(filtered.is_open());
+}
@@ -0,0 +1,93 @@
+use sidetone::{backend::mock::MockDevice, MockBackend, Route, Sidetone};
+use std::time::Duration;
+
+const RATE: u32 = 48_000;
+const PERIOD: usize = 480;
+
+fn backend() -> MockBackend {
+    MockBackend::new()
+        .with_input_device(MockDevice::new("mic", 1, RATE))
+        .with_output_device(MockDevice::new("headphones", 1, RATE))
+        .with_period(PERIOD)
+}
+
+/// Plays `input` through a route limited at `ceiling_db` and returns the route and the output
+/// aligned with the input.
+fn play(
+    backend: &MockBackend,
+    ceiling_db: f32,
+    input: &[f32],
+) -> anyhow::Result<(Route, Vec<f32>)> {
+    let route = Sidetone::new()
+        .latency(Duration::from_millis(20))
+        .fade_in(Duration::ZERO)
+        .ceiling_db(ceiling_db)
+        .build(backend)?;
+    route.start()?;
+    backend.push_input_blocks(input, PERIOD);
+    backend.run(input.len() / PERIOD + 4);
+    let start = (route.latency().as_secs_f64() * RATE as f64).round() as usize;
+    let output = backend.captured_output()[start..start + input.len()].to_vec();
+    Ok((route, output))
+}
+
+fn peak(samples: &[f32]) -> f32 {
+    samples.iter().fold(0.0, |peak, x| peak.max(x.abs()))
+}
+
+#[test]
+fn signals_below_the_ceiling_pass_unchanged() -> anyhow::Result<()> {
+    let input: Vec<f32> = (0..4800).map(|x| (x as f32 * 0.01).sin() * 0.5).collect();
+    let (route, output) = play(&backend(), -3.0, &input)?;
+
+    assert_eq!(output, input);
+    assert_eq!(route.stats().clip_events, 0);
+    Ok(())
+}
+
+#[test]
+fn bursts_never_exceed_the_ceiling_and_are_counted() -> anyhow::Result<()> {
+    let mut input = vec![0.01; 9600];
+    for burst in [1000..1500, 6000..6100] {
+        input[burst].iter_mut().for_each(|x| *x = 1.0);
+    }
+    let (route, output) = play(&backend(), -6.0, &input)?;
+
+    let ceiling = 10_f32.powf(-6.0 / 20.0);
+    assert!(peak(&output) <= ceiling);
+    // The gain is down by the time the burst arrives, a little further than the burst itself
+    // needs for the overshoot of its edge between samples.
+    assert!(output[1000] > 0.8 * ceiling, "{}", output[1000]);
+    assert_eq!(route.stats().clip_events, 2);
+    // And recovers over the release time afterwards.
+    assert!(output[1600] < 0.006);
+    assert!((output[5900] - 0.01).abs() < 1e-3);
+    Ok(())
+}
+
+#[test]
+fn intersample_peaks_are_limited() -> anyhow::Result<()> {
+    // A quarter of the sample rate at 45 degrees: every sample is at 0.707 of the true peak.
+    let input: Vec<f32> = (0..4800)
+        .map(|x| (x as f32 * std::f32::consts::FRAC_PI_2 + std::f32::consts::FRAC_PI_4).sin())
+        .collect();
+    assert!(peak(&input) < 0.71);
+    let (route, output) = play(&backend(), -1.0, &input)?;
+
+    let ceiling = 10_f32.powf(-1.0 / 20.0);
+    assert!(peak(&output[100..]) < ceiling * 0.75, "{}", peak(&output));
+    assert_eq!(route.stats().clip_events, 1);
+    Ok(())
+}
+
+#[test]
+fn limiter_lookahead_adds_to_the_route_latency() -> anyhow::Result<()> {
+    let backend = backend();
+    let limited = Sidetone::new()
+        .latency(Duration::from_millis(20))
+        .ceiling_db(0.0)
+        .build(&backend)?;
+    let extra = limited.latency() - Duration::from_millis(20);
+    assert!(extra > Duration::from_millis(1) && extra < Duration::from_millis(2));
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use sidetone::{backend::mock::MockDevice, measure::LatencyProbe, MockBackend, Sidetone};
+use std::time::Duration;
+
+const RATE: u32 = 8000;
+const PERIOD: usize = 64;
+
+fn backend(input_channels: u16, output_channels: u16) -> MockBackend {
+    MockBackend::new()
+        .with_input_device(MockDevice::new("mic", input_channels, RATE))
+        .with_output_device(MockDevice::new("headphones", output_channels, RATE))
+        .with_period(PERIOD)
+}
+
+fn capture(backend: &MockBackend, probe: &mut LatencyProbe) -> anyhow::Result<()> {
+    probe.start()?;
+    for _ in 0..1000 {
+        if probe.poll()? {
+            return Ok(());
+        }
+        backend.run_cycle();
+    }
+    anyhow::bail!("the capture never completed")
+}
+
+#[test]
+fn loopback_delay_is_measured() -> anyhow::Result<()> {
+    let backend = backend(1, 2).with_loopback(300);
+    let mut probe = LatencyProbe::build(&backend, &Sidetone::new(), Duration::from_millis(100))?;
+    capture(&backend, &mut probe)?;
+    let round_trip = probe.analyze()?;
+
+    assert_eq!(round_trip.frames, 300);
+    assert_eq!(round_trip.latency, Duration::from_micros(37_500));
+    assert!(round_trip.correlation > 0.99, "{}", round_trip.correlation);
+    Ok(())
+}
+
+#[test]
+fn delay_shorter_than_a_buffer_is_measured() -> anyhow::Result<()> {
+    let backend = backend(2, 2).with_loopback(5);
+    let sidetone = Sidetone::new().buffer_frames(256);
+    let mut probe = LatencyProbe::build(&backend, &sidetone, Duration::from_millis(100))?;
+    capture(&backend, &mut probe)?;
+
+    assert_eq!(probe.analyze()?.frames, 5);
+    Ok(())
+}
+
+#[test]
+fn missing_loopback_is_an_error() -> anyhow::Result<()> {
+    let backend = backend(1, 1);
+    let mut probe = LatencyProbe::build(&backend, &Sidetone::new(), Duration::from_millis(100))?;
+    backend.push_input_blocks(&vec![0.0; 64 * PERIOD * 40], PERIOD);
+    capture(&backend, &mut probe)?;
+
+    assert!(probe.analyze().is_err());
+    Ok(())
+}
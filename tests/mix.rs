@@ -0,0 +1,74 @@
+use sidetone::{backend::mock::MockDevice, mix::RoutingMatrix, MockBackend, Sidetone};
+use std::time::Duration;
+
+const RATE: u32 = 100;
+const PERIOD: usize = 10;
+
+fn backend(input_channels: u16, output_channels: u16) -> MockBackend {
+    MockBackend::new()
+        .with_input_device(MockDevice::new("interface", input_channels, RATE))
+        .with_output_device(MockDevice::new("headphones", output_channels, RATE))
+        .with_period(PERIOD)
+}
+
+/// `frames` interleaved frames in which channel `c` holds `c + 1`.
+fn numbered(frames: usize, channels: usize) -> Vec<f32> {
+    (0..frames)
+        .flat_map(|_| (1..=channels).map(|c| c as f32))
+        .collect()
+}
+
+fn route(matrix: &str, input_channels: u16, output_channels: u16) -> anyhow::Result<Vec<f32>> {
+    let backend = backend(input_channels, output_channels);
+    let route = Sidetone::new()
+        .latency(Duration::from_millis(100))
+        .routing(matrix.parse()?)
+        .build(&backend)?;
+    route.start()?;
+    backend.push_input_blocks(&numbered(20, input_channels as usize), PERIOD);
+    backend.run(3);
+    let output = backend.captured_output();
+    Ok(output[PERIOD * output_channels as usize..].to_vec())
+}
+
+#[test]
+fn matrix_is_parsed_with_one_based_channels_and_db_gains() -> anyhow::Result<()> {
+    let matrix: RoutingMatrix = "3:1, 5:2:-6".parse()?;
+    let expected = RoutingMatrix::new()
+        .connect(2, 0, 1.0)
+        .connect(4, 1, 10_f32.powf(-6.0 / 20.0));
+    assert_eq!(matrix, expected);
+    assert_eq!(matrix.to_string(), "3:1,5:2:-6.0");
+    Ok(())
+}
+
+#[test]
+fn malformed_matrix_is_rejected() {
+    for matrix in ["", "3", "0:1", "1:x", "1:2:loud", "1:2:3:4"] {
+        assert!(matrix.parse::<RoutingMatrix>().is_err(), "{}", matrix);
+    }
+}
+
+#[test]
+fn any_input_channel_reaches_any_output_channel() -> anyhow::Result<()> {
+    let output = route("3:1,5:2", 8, 2)?;
+    assert_eq!(output.len(), 40);
+    assert!(output.chunks(2).all(|x| x == [3.0, 5.0]), "{:?}", output);
+    Ok(())
+}
+
+#[test]
+fn crosspoints_into_one_output_are_summed_with_their_gains() -> anyhow::Result<()> {
+    let output = route("1:1,2:1:-6.0206,2:2", 2, 3)?;
+    for frame in output.chunks(3) {
+        assert!((frame[0] - 2.0).abs() < 1e-4, "{:?}", frame);
+        assert_eq!(frame[1..], [2.0, 0.0]);
+    }
+    Ok(())
+}
+
+#[test]
+fn channel_missing_from_the_device_is_an_error() {
+    assert!(route("3:1", 2, 2).is_err());
+    assert!(route("1:3", 2, 2).is_err());
+}
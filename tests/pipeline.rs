@@ -0,0 +1,578 @@
+use sidetone::{
+    backend::mock::MockDevice, config::StreamRequest, MockBackend, Processor, Sidetone,
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+const RATE: u32 = 100;
+const PERIOD: usize = 10;
+
+fn backend(input_channels: u16, output_channels: u16) -> MockBackend {
+    MockBackend::new()
+        .with_input_device(MockDevice::new("mic", input_channels, RATE))
+        .with_output_device(MockDevice::new("headphones", output_channels, RATE))
+        .with_period(PERIOD)
+}
+
+fn ramp(len: usize) -> Vec<f32> {
+    (1..=len).map(|x| x as f32).collect()
+}
+
+struct Offset(f32);
+
+impl Processor for Offset {
+    fn prepare(&mut self, _sample_rate: u32, _channels: u16) {}
+
+    fn process(&mut self, block: &mut [f32]) {
+        block.iter_mut().for_each(|x| *x += self.0);
+    }
+}
+
+struct Scale(f32);
+
+impl Processor for Scale {
+    fn prepare(&mut self, _sample_rate: u32, _channels: u16) {}
+
+    fn process(&mut self, block: &mut [f32]) {
+        block.iter_mut().for_each(|x| *x *= self.0);
+    }
+
+    fn latency(&self) -> usize {
+        25
+    }
+}
+
+#[test]
+fn input_is_delayed_by_latency() -> anyhow::Result<()> {
+    let backend = backend(1, 1);
+    let route = Sidetone::new()
+        .latency(Duration::from_secs(1))
+        .build(&backend)?;
+    route.start()?;
+    backend.push_input_blocks(&ramp(200), PERIOD);
+    backend.run(30);
+
+    let output = backend.captured_output();
+    assert_eq!(output.len(), 300);
+    assert!(output[..100].iter().all(|&x| x == 0.0));
+    assert_eq!(output[100..], ramp(200)[..]);
+    assert_eq!(route.stats().underruns, 0);
+    assert_eq!(route.stats().overruns, 0);
+    Ok(())
+}
+
+#[test]
+fn mono_input_is_copied_to_every_output_channel() -> anyhow::Result<()> {
+    let backend = backend(1, 2);
+    let route = Sidetone::new()
+        .latency(Duration::from_secs(1))
+        .build(&backend)?;
+    route.start()?;
+    backend.push_input_blocks(&ramp(100), PERIOD);
+    backend.run(30);
+
+    let output = backend.captured_output();
+    assert!(output.chunks(2).all(|x| x[0] == x[1]));
+    let signal: Vec<f32> = output.chunks(2).map(|x| x[0]).collect();
+    assert!(signal[..100].iter().all(|&x| x == 0.0));
+    assert_eq!(signal[100..200], ramp(100)[..]);
+    Ok(())
+}
+
+/// `frames` interleaved frames whose channel `c` counts up from `(c + 1) * 1000`.
+fn channels(frames: usize, channels: usize) -> Vec<f32> {
+    (0..frames)
+        .flat_map(|x| (0..channels).map(move |c| ((c + 1) * 1000 + x) as f32))
+        .collect()
+}
+
+#[test]
+fn stereo_input_passes_through_to_a_stereo_output() -> anyhow::Result<()> {
+    let backend = backend(2, 2);
+    let route = Sidetone::new()
+        .latency(Duration::from_secs(1))
+        .build(&backend)?;
+    route.start()?;
+    let input = channels(100, 2);
+    backend.push_input_blocks(&input, PERIOD);
+    backend.run(20);
+
+    let output = backend.captured_output();
+    assert_eq!(output.len(), 400);
+    assert!(output[..200].iter().all(|&x| x == 0.0));
+    assert_eq!(output[200..], input[..]);
+    assert_eq!(route.stats().underruns, 0);
+    Ok(())
+}
+
+#[test]
+fn stereo_input_is_averaged_into_a_mono_output() -> anyhow::Result<()> {
+    let backend = backend(2, 1);
+    let route = Sidetone::new()
+        .latency(Duration::from_secs(1))
+        .build(&backend)?;
+    route.start()?;
+    backend.push_input_blocks(&channels(100, 2), PERIOD);
+    backend.run(20);
+
+    let output = backend.captured_output();
+    let expected: Vec<f32> = (0..100).map(|x| 1500.0 + x as f32).collect();
+    assert_eq!(output[100..], expected[..]);
+    Ok(())
+}
+
+#[test]
+fn extra_input_channels_fold_into_stereo_pairs() -> anyhow::Result<()> {
+    let backend = backend(4, 2);
+    let route = Sidetone::new()
+        .latency(Duration::from_secs(1))
+        .build(&backend)?;
+    route.start()?;
+    backend.push_input_blocks(&channels(100, 4), PERIOD);
+    backend.run(20);
+
+    let output = backend.captured_output();
+    // Left averages channels 1 and 3, right channels 2 and 4.
+    let expected: Vec<f32> = (0..100)
+        .flat_map(|x| [2000.0 + x as f32, 3000.0 + x as f32])
+        .collect();
+    assert_eq!(output[200..], expected[..]);
+    Ok(())
+}
+
+#[test]
+fn surround_input_folds_into_stereo_with_centre_on_both_sides() -> anyhow::Result<()> {
+    let backend = backend(6, 2);
+    let route = Sidetone::new()
+        .latency(Duration::from_secs(1))
+        .build(&backend)?;
+    route.start()?;
+    backend.push_input_blocks(&channels(100, 6), PERIOD);
+    backend.run(20);
+
+    let output = backend.captured_output();
+    // L R C LFE Ls Rs: centre and surrounds at -3 dB, no LFE.
+    let side = std::f32::consts::FRAC_1_SQRT_2;
+    let norm = 1.0 + 2.0 * side;
+    let left = (1000.0 + side * 3000.0 + side * 5000.0) / norm;
+    let right = (2000.0 + side * 3000.0 + side * 6000.0) / norm;
+    for (x, frame) in output[200..].chunks(2).enumerate() {
+        assert!((frame[0] - left - x as f32).abs() < 0.1, "{:?}", frame);
+        assert!((frame[1] - right - x as f32).abs() < 0.1, "{:?}", frame);
+    }
+    Ok(())
+}
+
+#[test]
+fn odd_input_channel_is_spread_across_stereo() -> anyhow::Result<()> {
+    let backend = backend(3, 2);
+    let route = Sidetone::new()
+        .latency(Duration::from_secs(1))
+        .build(&backend)?;
+    route.start()?;
+    backend.push_input_blocks(&channels(100, 3), PERIOD);
+    backend.run(20);
+
+    let output = backend.captured_output();
+    let side = std::f32::consts::FRAC_1_SQRT_2;
+    let left = (1000.0 + side * 3000.0) / (1.0 + side);
+    let right = (2000.0 + side * 3000.0) / (1.0 + side);
+    for (x, frame) in output[200..].chunks(2).enumerate() {
+        assert!((frame[0] - left - x as f32).abs() < 0.1, "{:?}", frame);
+        assert!((frame[1] - right - x as f32).abs() < 0.1, "{:?}", frame);
+    }
+    Ok(())
+}
+
+#[test]
+fn stereo_input_repeats_across_more_output_channels() -> anyhow::Result<()> {
+    let backend = backend(2, 4);
+    let route = Sidetone::new()
+        .latency(Duration::from_secs(1))
+        .build(&backend)?;
+    route.start()?;
+    backend.push_input_blocks(&channels(100, 2), PERIOD);
+    backend.run(20);
+
+    let output = backend.captured_output();
+    assert!(output[400..]
+        .chunks(4)
+        .all(|x| x[0] == x[2] && x[1] == x[3] && x[1] - x[0] == 1000.0));
+    Ok(())
+}
+
+#[test]
+fn resampled_stereo_keeps_its_channels_apart() -> anyhow::Result<()> {
+    let backend = MockBackend::new()
+        .with_input_device(MockDevice::new("mic", 2, 50))
+        .with_output_device(MockDevice::new("headphones", 2, RATE))
+        .with_period(PERIOD);
+    let route = Sidetone::new()
+        .latency(Duration::from_secs(1))
+        .build(&backend)?;
+    route.start()?;
+    let input: Vec<f32> = (0..100).flat_map(|_| [0.25, -0.5]).collect();
+    backend.push_input_blocks(&input, PERIOD / 2);
+    backend.run(20);
+
+    let output = backend.captured_output();
+    assert_eq!(output.len(), 400);
+    assert_eq!(route.stats().underruns, 0);
+    for frame in output[2 * 116..].chunks(2) {
+        assert!((frame[0] - 0.25).abs() < 1e-3, "{:?}", frame);
+        assert!((frame[1] + 0.5).abs() < 1e-3, "{:?}", frame);
+    }
+    Ok(())
+}
+
+#[test]
+fn processors_run_in_order() -> anyhow::Result<()> {
+    let backend = backend(1, 1);
+    let route = Sidetone::new()
+        .latency(Duration::from_secs(1))
+        .processor(Offset(1.0))
+        .processor(Scale(2.0))
+        .build(&backend)?;
+    route.start()?;
+    backend.push_input_blocks(&ramp(100), PERIOD);
+    backend.run(20);
+
+    let output = backend.captured_output();
+    let expected: Vec<f32> = ramp(100).iter().map(|x| (x + 1.0) * 2.0).collect();
+    assert_eq!(output[100..], expected[..]);
+    Ok(())
+}
+
+#[test]
+fn callbacks_longer_than_the_default_period_are_rendered_in_chunks() -> anyhow::Result<()> {
+    let backend = MockBackend::new()
+        .with_input_device(MockDevice::new("mic", 1, 10_000))
+        .with_output_device(MockDevice::new("headphones", 1, 10_000))
+        .with_period(5000);
+    let route = Sidetone::new()
+        .latency(Duration::from_secs(1))
+        .fade_in(Duration::ZERO)
+        .build(&backend)?;
+    route.start()?;
+    backend.push_input_blocks(&ramp(20_000), 5000);
+    backend.run(6);
+
+    let output = backend.captured_output();
+    assert!(output[..10_000].iter().all(|&x| x == 0.0));
+    assert_eq!(output[10_000..], ramp(20_000)[..]);
+    assert_eq!(route.stats().underruns, 0);
+    Ok(())
+}
+
+/// Records what the route asks of it.
+struct Recorder(Arc<Mutex<Vec<String>>>);
+
+impl Processor for Recorder {
+    fn prepare(&mut self, sample_rate: u32, channels: u16) {
+        let mut calls = self.0.lock().unwrap_or_else(|x| x.into_inner());
+        calls.push(format!("prepare {} Hz {} channels", sample_rate, channels));
+    }
+
+    fn process(&mut self, block: &mut [f32]) {
+        let mut calls = self.0.lock().unwrap_or_else(|x| x.into_inner());
+        calls.push(format!("process {} samples", block.len()));
+    }
+}
+
+#[test]
+fn processors_are_prepared_for_the_output_stream() -> anyhow::Result<()> {
+    let backend = MockBackend::new()
+        .with_input_device(MockDevice::new("mic", 1, 50))
+        .with_output_device(MockDevice::new("headphones", 2, RATE))
+        .with_period(PERIOD);
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let route = Sidetone::new()
+        .latency(Duration::from_secs(1))
+        .processor(Recorder(Arc::clone(&calls)))
+        .build(&backend)?;
+    route.start()?;
+    backend.run(2);
+
+    let calls = calls.lock().unwrap_or_else(|x| x.into_inner());
+    assert_eq!(
+        *calls,
+        [
+            "prepare 100 Hz 2 channels",
+            "process 20 samples",
+            "process 20 samples"
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn latency_includes_processor_latency() -> anyhow::Result<()> {
+    let backend = backend(1, 1);
+    let route = Sidetone::new()
+        .latency(Duration::from_secs(1))
+        .processor(Scale(1.0))
+        .build(&backend)?;
+    assert_eq!(route.latency(), Duration::from_millis(1250));
+    Ok(())
+}
+
+#[test]
+fn underrun_is_counted_when_input_stops() -> anyhow::Result<()> {
+    let backend = backend(1, 1);
+    let route = Sidetone::new()
+        .latency(Duration::from_secs(1))
+        .build(&backend)?;
+    route.start()?;
+    backend.run(11);
+    assert_eq!(route.stats().underruns, 1);
+    Ok(())
+}
+
+#[test]
+fn overrun_is_counted_when_output_stops() -> anyhow::Result<()> {
+    let backend = backend(1, 1);
+    let route = Sidetone::new()
+        .latency(Duration::from_secs(1))
+        .build(&backend)?;
+    route.start()?;
+    backend.push_input(vec![0.5; 10_000]);
+    backend.run(1);
+    assert_eq!(route.stats().overruns, 1);
+    Ok(())
+}
+
+#[test]
+fn stopped_route_produces_no_output() -> anyhow::Result<()> {
+    let backend = backend(1, 1);
+    let route = Sidetone::new().build(&backend)?;
+    backend.run(5);
+    route.start()?;
+    route.stop()?;
+    backend.run(5);
+    assert!(backend.captured_output().is_empty());
+    Ok(())
+}
+
+#[test]
+fn devices_are_selected_by_name() -> anyhow::Result<()> {
+    let backend = backend(1, 1)
+        .with_input_device(MockDevice::new("usb headset", 1, RATE))
+        .with_output_device(MockDevice::new("usb headset", 2, RATE));
+    let route = Sidetone::new()
+        .input_device("usb headset")
+        .output_device("usb headset")
+        .build(&backend)?;
+    assert_eq!(route.input_name(), "usb headset");
+    assert_eq!(route.output_name(), "usb headset");
+    Ok(())
+}
+
+#[test]
+fn unknown_device_is_an_error() {
+    let backend = backend(1, 1);
+    let result = Sidetone::new().input_device("missing").build(&backend);
+    assert!(result.is_err());
+}
+
+#[test]
+fn mismatched_sample_rates_are_resampled() -> anyhow::Result<()> {
+    let backend = MockBackend::new()
+        .with_input_device(MockDevice::new("mic", 1, 50))
+        .with_output_device(MockDevice::new("headphones", 1, RATE))
+        .with_period(PERIOD);
+    let route = Sidetone::new()
+        .latency(Duration::from_secs(1))
+        .build(&backend)?;
+    route.start()?;
+    backend.push_input_blocks(&[0.5; 100], PERIOD / 2);
+    backend.run(20);
+
+    let output = backend.captured_output();
+    assert_eq!(output.len(), 200);
+    assert_eq!(route.stats().underruns, 0);
+    // 50 frames of priming at 50 Hz come out as 100 frames at 100 Hz; the resampler kernel
+    // smears the step into the 16 output frames on each side of it.
+    assert!(output[..84].iter().all(|x| x.abs() < 1e-6));
+    assert!(output[116..].iter().all(|x| (x - 0.5).abs() < 1e-3));
+    Ok(())
+}
+
+#[test]
+fn drift_compensation_holds_the_buffer_level() -> anyhow::Result<()> {
+    // The input clock runs 1000 ppm fast: 1001 frames arrive for every 1000 played.
+    let backend = MockBackend::new()
+        .with_input_device(MockDevice::new("mic", 1, 1000))
+        .with_output_device(MockDevice::new("headphones", 1, 1000))
+        .with_period(1000);
+    let route = Sidetone::new()
+        .latency(Duration::from_secs(1))
+        .drift_compensation(true)
+        .build(&backend)?;
+    route.start()?;
+    let mut calibrated = 0;
+    for cycle in 0..3000 {
+        backend.push_input(vec![0.5; 1001]);
+        backend.run_cycle();
+        backend.take_output();
+        if cycle == 5 {
+            calibrated = route.stats().buffer_level;
+        }
+    }
+
+    let stats = route.stats();
+    assert_eq!(stats.overruns, 0);
+    assert_eq!(stats.underruns, 0);
+    assert!(stats.buffer_level.abs_diff(calibrated) < 20, "{:?}", stats);
+    assert!(stats.drift_ppm.abs_diff(1000) < 100, "{:?}", stats);
+    Ok(())
+}
+
+#[test]
+fn sub_second_latency_is_primed() -> anyhow::Result<()> {
+    let backend = backend(1, 1);
+    let route = Sidetone::new()
+        .latency(Duration::from_millis(250))
+        .build(&backend)?;
+    route.start()?;
+    backend.push_input_blocks(&ramp(100), PERIOD);
+    backend.run(10);
+
+    let output = backend.captured_output();
+    assert!(output[..25].iter().all(|&x| x == 0.0));
+    assert_eq!(output[25..], ramp(75)[..]);
+    Ok(())
+}
+
+#[test]
+fn fixed_buffer_size_is_requested_from_the_device() -> anyhow::Result<()> {
+    let backend = backend(1, 1);
+    let route = Sidetone::new()
+        .latency(Duration::from_millis(500))
+        .buffer_frames(25)
+        .build(&backend)?;
+    route.start()?;
+    backend.run(1);
+    assert_eq!(backend.captured_output().len(), 25);
+    Ok(())
+}
+
+#[test]
+fn unsupported_buffer_size_is_rejected() {
+    let backend = MockBackend::new()
+        .with_input_device(
+            MockDevice::new("mic", 1, RATE)
+                .with_buffer_size_range(cpal::SupportedBufferSize::Range { min: 32, max: 64 }),
+        )
+        .with_output_device(MockDevice::new("headphones", 1, RATE));
+    let result = Sidetone::new()
+        .latency(Duration::from_secs(1))
+        .buffer_frames(16)
+        .build(&backend);
+    assert!(result.is_err());
+}
+
+#[test]
+fn latency_shorter_than_a_buffer_is_rejected() {
+    let backend = backend(1, 1);
+    let result = Sidetone::new()
+        .latency(Duration::from_millis(100))
+        .buffer_frames(20)
+        .build(&backend);
+    assert!(result.is_err());
+}
+
+/// A route at 1000 Hz whose input callbacks are 16 frames and output callbacks 160 frames.
+fn mismatched_buffers(latency: Duration) -> Sidetone {
+    Sidetone::new()
+        .latency(latency)
+        .input_config(StreamRequest {
+            buffer_frames: Some(16),
+            ..StreamRequest::default()
+        })
+        .output_config(StreamRequest {
+            buffer_frames: Some(160),
+            ..StreamRequest::default()
+        })
+}
+
+#[test]
+fn latency_shorter_than_the_output_buffer_is_rejected() {
+    let backend = MockBackend::new()
+        .with_input_device(MockDevice::new("mic", 1, 1000))
+        .with_output_device(MockDevice::new("headphones", 1, 1000));
+    let result = mismatched_buffers(Duration::from_millis(100)).build(&backend);
+    assert!(result.is_err());
+}
+
+#[test]
+fn mismatched_buffer_sizes_do_not_overrun() -> anyhow::Result<()> {
+    let backend = MockBackend::new()
+        .with_input_device(MockDevice::new("mic", 1, 1000))
+        .with_output_device(MockDevice::new("headphones", 1, 1000));
+    let route = mismatched_buffers(Duration::from_millis(160)).build(&backend)?;
+    route.start()?;
+    // Ten small input callbacks arrive for every large output callback.
+    for _ in 0..50 {
+        for _ in 0..9 {
+            backend.push_input([0.5; 16]);
+            backend.run_input();
+        }
+        backend.push_input([0.5; 16]);
+        backend.run_cycle();
+    }
+    assert_eq!(route.stats().overruns, 0);
+    assert_eq!(route.stats().underruns, 0);
+    Ok(())
+}
+
+#[test]
+fn auto_latency_starts_at_one_buffer() -> anyhow::Result<()> {
+    let backend = backend(1, 1);
+    let route = Sidetone::new()
+        .auto_latency(Duration::from_secs(1))
+        .build(&backend)?;
+    route.start()?;
+    backend.push_input_blocks(&ramp(200), PERIOD);
+    backend.run(20);
+
+    assert_eq!(route.latency(), Duration::from_millis(100));
+    assert_eq!(backend.captured_output(), ramp(200));
+    assert_eq!(route.stats().underruns, 0);
+    Ok(())
+}
+
+#[test]
+fn auto_latency_rises_after_glitches_and_settles_back() -> anyhow::Result<()> {
+    let backend = backend(1, 1);
+    let route = Sidetone::new()
+        .auto_latency(Duration::from_secs(1))
+        .build(&backend)?;
+    route.start()?;
+    // Every tenth input callback comes one period late, so one buffer is not enough.
+    for cycle in 0..300 {
+        if cycle % 10 != 9 {
+            backend.push_input([0.5; PERIOD]);
+            if cycle % 10 == 0 && cycle > 0 {
+                backend.push_input([0.5; PERIOD]);
+                backend.run_input();
+            }
+        }
+        backend.run_cycle();
+    }
+    let jittery = route.latency();
+    let underruns = route.stats().underruns;
+    assert!(jittery >= Duration::from_millis(200), "{:?}", jittery);
+    assert!(jittery <= Duration::from_millis(250), "{:?}", jittery);
+    assert!(underruns <= 5, "{}", underruns);
+
+    // Once the input is steady the tuner steps back down to one buffer without glitching.
+    for _ in 0..3000 {
+        backend.push_input([0.5; PERIOD]);
+        backend.run_cycle();
+    }
+    assert_eq!(route.latency(), Duration::from_millis(100));
+    assert_eq!(route.stats().underruns, underruns);
+    Ok(())
+}
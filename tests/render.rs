@@ -0,0 +1,108 @@
+use sidetone::{
+    render::{read_wav, render, render_file, write_wav, Audio},
+    Processor, Sidetone,
+};
+use std::{path::PathBuf, time::Duration};
+
+const RATE: u32 = 1000;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("sidetone-{}-{}", std::process::id(), name))
+}
+
+fn sine(frames: usize) -> Vec<f32> {
+    (0..frames).map(|x| (x as f32 * 0.05).sin() * 0.5).collect()
+}
+
+struct Half;
+
+impl Processor for Half {
+    fn prepare(&mut self, _sample_rate: u32, _channels: u16) {}
+
+    fn process(&mut self, block: &mut [f32]) {
+        block.iter_mut().for_each(|x| *x *= 0.5);
+    }
+}
+
+#[test]
+fn render_delays_input_by_latency() -> anyhow::Result<()> {
+    let input = Audio {
+        sample_rate: RATE,
+        channels: 1,
+        samples: sine(700),
+    };
+    let sidetone = Sidetone::new().latency(Duration::from_secs(1));
+    let (output, stats) = render(sidetone, &input, 1)?;
+
+    assert_eq!(output.sample_rate, RATE);
+    assert_eq!(output.frames(), 1700);
+    assert!(output.samples[..1000].iter().all(|&x| x == 0.0));
+    assert_eq!(output.samples[1000..], input.samples[..]);
+    assert_eq!(stats.underruns, 0);
+    assert_eq!(stats.overruns, 0);
+    Ok(())
+}
+
+#[test]
+fn render_runs_the_processing_chain() -> anyhow::Result<()> {
+    let input = Audio {
+        sample_rate: RATE,
+        channels: 1,
+        samples: sine(300),
+    };
+    let sidetone = Sidetone::new()
+        .latency(Duration::from_secs(1))
+        .processor(Half);
+    let (output, _) = render(sidetone, &input, 1)?;
+
+    let expected: Vec<f32> = input.samples.iter().map(|x| x * 0.5).collect();
+    assert_eq!(output.samples[1000..], expected[..]);
+    Ok(())
+}
+
+#[test]
+fn render_file_reads_integer_wav_and_writes_float_wav() -> anyhow::Result<()> {
+    let input_path = temp_path("render-in.wav");
+    let output_path = temp_path("render-out.wav");
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(&input_path, spec)?;
+    for x in [0_i16, 16384, -16384, i16::MIN] {
+        writer.write_sample(x)?;
+    }
+    writer.finalize()?;
+
+    render_file(
+        Sidetone::new().latency(Duration::from_secs(1)),
+        &input_path,
+        &output_path,
+        None,
+    )?;
+    let output = read_wav(&output_path)?;
+    std::fs::remove_file(&input_path)?;
+    std::fs::remove_file(&output_path)?;
+
+    assert_eq!(output.channels, 1);
+    assert_eq!(output.sample_rate, RATE);
+    assert!(output.samples.ends_with(&[0.0, 0.5, -0.5, -1.0]));
+    Ok(())
+}
+
+#[test]
+fn wav_round_trip_is_lossless_for_float() -> anyhow::Result<()> {
+    let path = temp_path("round-trip.wav");
+    let audio = Audio {
+        sample_rate: 48_000,
+        channels: 2,
+        samples: sine(64),
+    };
+    write_wav(&path, &audio)?;
+    let read = read_wav(&path)?;
+    std::fs::remove_file(&path)?;
+    assert_eq!(read, audio);
+    Ok(())
+}
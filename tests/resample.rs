@@ -0,0 +1,82 @@
+use sidetone::resample::{Quality, Resampler};
+use std::f32::consts::PI;
+
+fn sine(frequency: f32, rate: u32, frames: usize) -> Vec<f32> {
+    (0..frames)
+        .map(|x| (2.0 * PI * frequency * x as f32 / rate as f32).sin())
+        .collect()
+}
+
+fn resample(input: &[f32], input_rate: u32, output_rate: u32, quality: Quality) -> Vec<f32> {
+    let mut resampler = Resampler::new(input_rate, output_rate, 1, quality);
+    resampler.push(input);
+    let mut output = vec![0.0; input.len() * output_rate as usize / input_rate as usize];
+    let produced = resampler.pull(&mut output);
+    output.truncate(produced);
+    output
+}
+
+fn max_error(actual: &[f32], expected: &[f32]) -> f32 {
+    actual
+        .iter()
+        .zip(expected)
+        .map(|(x, y)| (x - y).abs())
+        .fold(0.0, f32::max)
+}
+
+#[test]
+fn upsampling_preserves_a_tone() {
+    let input = sine(1000.0, 16_000, 1600);
+    let expected = sine(1000.0, 48_000, 4800);
+    for (quality, tolerance) in [
+        (Quality::Fast, 0.05),
+        (Quality::Balanced, 0.01),
+        (Quality::High, 0.002),
+    ] {
+        let output = resample(&input, 16_000, 48_000, quality);
+        assert!(output.len() > 4000);
+        let error = max_error(&output[200..4000], &expected[200..4000]);
+        assert!(error < tolerance, "{} quality error {}", quality, error);
+    }
+}
+
+#[test]
+fn downsampling_suppresses_content_above_nyquist() {
+    let input = sine(12_000.0, 48_000, 4800);
+    let output = resample(&input, 48_000, 16_000, Quality::High);
+    let peak = output[100..].iter().fold(0.0_f32, |x, y| x.max(y.abs()));
+    assert!(peak < 0.05, "aliased peak {}", peak);
+}
+
+#[test]
+fn output_does_not_depend_on_block_sizes() {
+    let input = sine(440.0, 44_100, 2000);
+    let whole = resample(&input, 44_100, 48_000, Quality::Balanced);
+
+    let mut resampler = Resampler::new(44_100, 48_000, 1, Quality::Balanced);
+    let mut chunked = Vec::new();
+    let mut block = [0.0; 37];
+    for chunk in input.chunks(53) {
+        resampler.push(chunk);
+        loop {
+            let produced = resampler.pull(&mut block);
+            chunked.extend_from_slice(&block[..produced]);
+            if produced < block.len() {
+                break;
+            }
+        }
+    }
+    let len = whole.len().min(chunked.len());
+    assert!(len > 2000);
+    assert_eq!(whole[..len], chunked[..len]);
+}
+
+#[test]
+fn input_frames_needed_is_exact() {
+    let mut resampler = Resampler::new(48_000, 16_000, 2, Quality::High);
+    let needed = resampler.input_frames_needed(100);
+    resampler.push(&vec![0.25; needed * 2]);
+    let mut output = vec![0.0; 200];
+    assert_eq!(resampler.pull(&mut output), 100);
+    assert_eq!(resampler.input_frames_needed(1), 3);
+}
@@ -0,0 +1,33 @@
+use sidetone::{eq::EqSettings, mix::RoutingMatrix, settings::SettingsFile};
+
+#[test]
+fn route_uses_the_command_line_syntax() -> anyhow::Result<()> {
+    let file: SettingsFile = r#"{ "route": "3:1,5:2:-6" }"#.parse()?;
+    assert_eq!(file.route, Some("3:1,5:2:-6".parse::<RoutingMatrix>()?));
+    Ok(())
+}
+
+#[test]
+fn eq_uses_the_command_line_syntax() -> anyhow::Result<()> {
+    let file: SettingsFile = r#"{ "eq": "highpass:100,peak:250:-4:1.5" }"#.parse()?;
+    assert_eq!(
+        file.eq,
+        Some("highpass:100,peak:250:-4:1.5".parse::<EqSettings>()?)
+    );
+    assert_eq!(file.route, None);
+    Ok(())
+}
+
+#[test]
+fn missing_fields_keep_their_default() -> anyhow::Result<()> {
+    let file: SettingsFile = "{}".parse()?;
+    assert_eq!(file, SettingsFile::default());
+    Ok(())
+}
+
+#[test]
+fn invalid_settings_are_rejected() {
+    assert!(r#"{ "route": "0:1" }"#.parse::<SettingsFile>().is_err());
+    assert!(r#"{ "rout": "1:1" }"#.parse::<SettingsFile>().is_err());
+    assert!(r#"{ "eq": "peak:250" }"#.parse::<SettingsFile>().is_err());
+}
@@ -0,0 +1,144 @@
+use sidetone::{
+    backend::mock::MockDevice,
+    mix::RoutingMatrix,
+    supervisor::{RecoveryPolicy, Supervisor},
+    MockBackend, Sidetone,
+};
+use std::time::Duration;
+
+fn policy() -> RecoveryPolicy {
+    RecoveryPolicy {
+        initial_backoff: Duration::ZERO,
+        max_backoff: Duration::ZERO,
+        preferred_check_interval: Duration::ZERO,
+        ..RecoveryPolicy::default()
+    }
+}
+
+fn backend() -> MockBackend {
+    MockBackend::new()
+        .with_input_device(MockDevice::new("built-in", 1, 48_000))
+        .with_output_device(MockDevice::new("built-in", 2, 48_000))
+        .with_input_device(MockDevice::new("headset", 1, 48_000))
+}
+
+fn headset() -> Sidetone {
+    Sidetone::new().input_device("headset")
+}
+
+#[test]
+fn route_is_rebuilt_when_the_device_comes_back() -> anyhow::Result<()> {
+    let backend = backend();
+    let mut supervisor = Supervisor::new(backend.clone(), headset, policy());
+    supervisor.poll()?;
+    assert_eq!(backend.input_stream_device().as_deref(), Some("headset"));
+
+    backend.remove_device("headset");
+    backend.fail(cpal::StreamError::DeviceNotAvailable);
+    supervisor.poll()?;
+    assert!(supervisor.route().is_none());
+    assert_eq!(backend.input_stream_device(), None);
+    supervisor.poll()?;
+    assert!(supervisor.route().is_none());
+
+    backend.add_input_device(MockDevice::new("headset", 1, 48_000));
+    supervisor.poll()?;
+    assert!(supervisor.route().is_some());
+    assert_eq!(backend.input_stream_device().as_deref(), Some("headset"));
+    assert_eq!(supervisor.restarts(), 1);
+    Ok(())
+}
+
+#[test]
+fn route_falls_back_to_default_devices() -> anyhow::Result<()> {
+    let backend = backend();
+    let policy = RecoveryPolicy {
+        fallback_to_default: true,
+        ..policy()
+    };
+    let mut supervisor = Supervisor::new(backend.clone(), headset, policy);
+    supervisor.poll()?;
+
+    backend.remove_device("headset");
+    backend.fail(cpal::StreamError::DeviceNotAvailable);
+    supervisor.poll()?;
+    supervisor.poll()?;
+    assert!(supervisor.is_on_fallback());
+    assert_eq!(backend.input_stream_device().as_deref(), Some("built-in"));
+
+    backend.add_input_device(MockDevice::new("headset", 1, 48_000));
+    supervisor.poll()?;
+    supervisor.poll()?;
+    assert!(!supervisor.is_on_fallback());
+    assert_eq!(backend.input_stream_device().as_deref(), Some("headset"));
+    Ok(())
+}
+
+#[test]
+fn repeated_stream_errors_restart_the_route() -> anyhow::Result<()> {
+    let backend = backend();
+    let policy = RecoveryPolicy {
+        error_budget: 2,
+        ..policy()
+    };
+    let mut supervisor = Supervisor::new(backend.clone(), headset, policy);
+    supervisor.poll()?;
+    let xrun = || cpal::StreamError::BackendSpecific {
+        err: cpal::BackendSpecificError {
+            description: String::from("xrun"),
+        },
+    };
+
+    backend.fail(xrun());
+    supervisor.poll()?;
+    assert_eq!(supervisor.restarts(), 0);
+    backend.fail(xrun());
+    supervisor.poll()?;
+    assert_eq!(supervisor.restarts(), 1);
+    Ok(())
+}
+
+#[test]
+fn supervisor_gives_up_after_max_retries() -> anyhow::Result<()> {
+    let backend = backend();
+    let policy = RecoveryPolicy {
+        max_retries: Some(2),
+        ..policy()
+    };
+    let mut supervisor = Supervisor::new(backend.clone(), headset, policy);
+    backend.remove_device("headset");
+    supervisor.poll()?;
+    supervisor.poll()?;
+    assert!(supervisor.poll().is_err());
+    Ok(())
+}
+
+#[test]
+fn invalid_settings_are_not_retried() {
+    let backend = backend();
+    let policy = RecoveryPolicy {
+        fallback_to_default: true,
+        ..policy()
+    };
+    let ceiling = || headset().ceiling_db(3.0);
+    let mut supervisor = Supervisor::new(backend.clone(), ceiling, policy.clone());
+    assert!(supervisor.poll().is_err());
+    assert!(supervisor.route().is_none());
+
+    // The output device has two channels.
+    let routing = || headset().routing(RoutingMatrix::new().connect(0, 2, 1.0));
+    let mut supervisor = Supervisor::new(backend, routing, policy);
+    assert!(supervisor.poll().is_err());
+}
+
+#[test]
+fn malformed_device_selector_is_not_retried() {
+    let policy = RecoveryPolicy {
+        fallback_to_default: true,
+        ..policy()
+    };
+    let selector = || Sidetone::new().input_device("re:[");
+    let mut supervisor = Supervisor::new(backend(), selector, policy);
+    assert!(supervisor.poll().is_err());
+    assert!(supervisor.route().is_none());
+}